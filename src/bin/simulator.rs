@@ -0,0 +1,46 @@
+//! Replays collateral corrections from a CSV of price ticks and trades, printing
+//! one CSV row per tick; see `oracle_program::simulator` for the input format and
+//! what a replay does not model
+//!
+//! Usage: simulator <events.csv>
+
+use std::{env, fs, process};
+
+use oracle_program::simulator::{self, REPORT_HEADER};
+
+const USAGE: &str = "Usage: simulator <events.csv>
+
+Replays collateral corrections from a CSV of price ticks and trades.
+See the oracle_program::simulator docs for the input format and what a
+replay does not model.";
+
+fn main() {
+    let path = match env::args().nth(1) {
+        Some(path) if path != "-h" && path != "--help" => path,
+        Some(_) => {
+            println!("{}", USAGE);
+            process::exit(0);
+        }
+        None => {
+            eprintln!("{}", USAGE);
+            process::exit(2);
+        }
+    };
+    let csv = fs::read_to_string(&path).unwrap_or_else(|err| {
+        eprintln!("Failed to read {}: {}", path, err);
+        process::exit(1);
+    });
+
+    match simulator::run(&csv) {
+        Ok(reports) => {
+            println!("{}", REPORT_HEADER);
+            for report in reports {
+                println!("{}", report);
+            }
+        }
+        Err(err) => {
+            eprintln!("{}: {}", path, err);
+            process::exit(1);
+        }
+    }
+}
@@ -0,0 +1,149 @@
+// inside cpi/amm.rs
+use solana_program::{
+    account_info::AccountInfo,
+    entrypoint::ProgramResult,
+    instruction::{AccountMeta, Instruction},
+    pubkey::Pubkey,
+};
+
+use super::{instruction_data, OracleSigner};
+
+const TRADE_USDI: u8 = 3;
+const TRADE_IASSET: u8 = 4;
+const MINT_IASSET: u8 = 5;
+const BURN_IASSET: u8 = 6;
+
+/// Accounts for the AMM's TradeUSDI and TradeiAsset instructions
+pub struct TradeAccounts<'a, 'info> {
+    pub authority: &'a AccountInfo<'info>,
+    pub user_account: &'a AccountInfo<'info>,
+    pub user_usdi_token_account: &'a AccountInfo<'info>,
+    pub user_iasset_token_account: &'a AccountInfo<'info>,
+    pub amm_pda_usdi_token_account: &'a AccountInfo<'info>,
+    pub amm_pda_iasset_token_account: &'a AccountInfo<'info>,
+    pub amm_account: &'a AccountInfo<'info>,
+    pub token_program: &'a AccountInfo<'info>,
+    pub amm_pda_account: &'a AccountInfo<'info>,
+}
+
+/// Accounts for the AMM's MintiAsset and BurniAsset instructions
+pub struct SupplyAccounts<'a, 'info> {
+    pub authority: &'a AccountInfo<'info>,
+    pub amm_iasset_token_account: &'a AccountInfo<'info>,
+    pub iasset_mint_account: &'a AccountInfo<'info>,
+    pub amm_account: &'a AccountInfo<'info>,
+    pub token_program: &'a AccountInfo<'info>,
+    pub amm_pda_account: &'a AccountInfo<'info>,
+}
+
+/// Trades {amount} of the user's USDI to the AMM for iAsset
+pub fn trade_usdi(
+    amm_program_id: &Pubkey,
+    accounts: TradeAccounts,
+    amount: u64,
+    signer: OracleSigner,
+) -> ProgramResult {
+    trade(amm_program_id, TRADE_USDI, accounts, amount, signer)
+}
+
+/// Trades {amount} of the user's iAsset to the AMM for USDI
+pub fn trade_iasset(
+    amm_program_id: &Pubkey,
+    accounts: TradeAccounts,
+    amount: u64,
+    signer: OracleSigner,
+) -> ProgramResult {
+    trade(amm_program_id, TRADE_IASSET, accounts, amount, signer)
+}
+
+/// Mints {amount} iAsset into the AMM's iAsset token account
+pub fn mint_iasset(
+    amm_program_id: &Pubkey,
+    accounts: SupplyAccounts,
+    amount: u64,
+    signer: OracleSigner,
+) -> ProgramResult {
+    change_supply(amm_program_id, MINT_IASSET, accounts, amount, signer)
+}
+
+/// Burns {amount} iAsset from the AMM's iAsset token account
+pub fn burn_iasset(
+    amm_program_id: &Pubkey,
+    accounts: SupplyAccounts,
+    amount: u64,
+    signer: OracleSigner,
+) -> ProgramResult {
+    change_supply(amm_program_id, BURN_IASSET, accounts, amount, signer)
+}
+
+fn trade(
+    amm_program_id: &Pubkey,
+    tag: u8,
+    accounts: TradeAccounts,
+    amount: u64,
+    signer: OracleSigner,
+) -> ProgramResult {
+    let instruction = Instruction {
+        program_id: *amm_program_id,
+        accounts: vec![
+            AccountMeta::new(*accounts.authority.key, true),
+            AccountMeta::new_readonly(*accounts.user_account.key, true),
+            AccountMeta::new(*accounts.user_usdi_token_account.key, false),
+            AccountMeta::new(*accounts.user_iasset_token_account.key, false),
+            AccountMeta::new(*accounts.amm_pda_usdi_token_account.key, false),
+            AccountMeta::new(*accounts.amm_pda_iasset_token_account.key, false),
+            AccountMeta::new(*accounts.amm_account.key, false),
+            AccountMeta::new_readonly(*accounts.token_program.key, false),
+            AccountMeta::new_readonly(*accounts.amm_pda_account.key, false),
+        ],
+        data: instruction_data(tag, amount),
+    };
+
+    signer.invoke_signed(
+        &instruction,
+        &[
+            accounts.authority.clone(),
+            accounts.user_account.clone(),
+            accounts.user_usdi_token_account.clone(),
+            accounts.user_iasset_token_account.clone(),
+            accounts.amm_pda_usdi_token_account.clone(),
+            accounts.amm_pda_iasset_token_account.clone(),
+            accounts.amm_account.clone(),
+            accounts.token_program.clone(),
+            accounts.amm_pda_account.clone(),
+        ],
+    )
+}
+
+fn change_supply(
+    amm_program_id: &Pubkey,
+    tag: u8,
+    accounts: SupplyAccounts,
+    amount: u64,
+    signer: OracleSigner,
+) -> ProgramResult {
+    let instruction = Instruction {
+        program_id: *amm_program_id,
+        accounts: vec![
+            AccountMeta::new(*accounts.authority.key, true),
+            AccountMeta::new(*accounts.amm_iasset_token_account.key, false),
+            AccountMeta::new(*accounts.iasset_mint_account.key, false),
+            AccountMeta::new(*accounts.amm_account.key, false),
+            AccountMeta::new_readonly(*accounts.token_program.key, false),
+            AccountMeta::new_readonly(*accounts.amm_pda_account.key, false),
+        ],
+        data: instruction_data(tag, amount),
+    };
+
+    signer.invoke_signed(
+        &instruction,
+        &[
+            accounts.authority.clone(),
+            accounts.amm_iasset_token_account.clone(),
+            accounts.iasset_mint_account.clone(),
+            accounts.amm_account.clone(),
+            accounts.token_program.clone(),
+            accounts.amm_pda_account.clone(),
+        ],
+    )
+}
@@ -0,0 +1,62 @@
+// inside cpi/mod.rs
+//! Typed clients for the AMM, UCP and LPCP programs the Oracle calls into.
+//!
+//! Each function builds the instruction with the target program's tag and
+//! account order and invokes it signed by the Oracle PDA.
+
+use solana_program::{
+    account_info::AccountInfo,
+    entrypoint::ProgramResult,
+    instruction::Instruction,
+    program::invoke_signed,
+    program_error::ProgramError,
+    pubkey::Pubkey,
+};
+
+pub mod amm;
+pub mod lpcp;
+pub mod ucp;
+
+/// Seed prefix of the Oracle PDA, followed by the Oracle account key
+pub const PDA_SEED: &[u8] = b"incept";
+
+/// Derives the PDA that holds authority for the given Oracle account
+pub fn find_oracle_pda(oracle_key: &Pubkey, program_id: &Pubkey) -> (Pubkey, u8) {
+    Pubkey::find_program_address(&[PDA_SEED, oracle_key.as_ref()], program_id)
+}
+
+/// Signs CPIs as the PDA of one Oracle account
+#[derive(Clone, Copy)]
+pub struct OracleSigner<'a> {
+    pub oracle_key: &'a Pubkey,
+    pub bump_seed: u8,
+}
+
+impl OracleSigner<'_> {
+    /// Recreates the PDA address from the stored bump, without searching for it
+    pub fn address(&self, program_id: &Pubkey) -> Result<Pubkey, ProgramError> {
+        Pubkey::create_program_address(
+            &[PDA_SEED, self.oracle_key.as_ref(), &[self.bump_seed]],
+            program_id,
+        )
+        .map_err(|_| ProgramError::InvalidSeeds)
+    }
+
+    pub fn invoke_signed(
+        &self,
+        instruction: &Instruction,
+        account_infos: &[AccountInfo],
+    ) -> ProgramResult {
+        invoke_signed(
+            instruction,
+            account_infos,
+            &[&[PDA_SEED, self.oracle_key.as_ref(), &[self.bump_seed]]],
+        )
+    }
+}
+
+fn instruction_data(tag: u8, amount: u64) -> Vec<u8> {
+    let mut data = vec![tag];
+    data.extend_from_slice(&amount.to_le_bytes());
+    data
+}
@@ -0,0 +1,54 @@
+// inside cpi/ucp.rs
+use solana_program::{
+    account_info::AccountInfo,
+    entrypoint::ProgramResult,
+    instruction::{AccountMeta, Instruction},
+    pubkey::Pubkey,
+};
+
+use super::{instruction_data, OracleSigner};
+
+const SEND_COLLATERAL: u8 = 3;
+
+/// Accounts for the UCP's SendCollateralLPCP instruction
+pub struct SendCollateralAccounts<'a, 'info> {
+    pub authority: &'a AccountInfo<'info>,
+    pub ucp_collateral_token_account: &'a AccountInfo<'info>,
+    pub lpcp_collateral_token_account: &'a AccountInfo<'info>,
+    pub ucp_account: &'a AccountInfo<'info>,
+    pub token_program: &'a AccountInfo<'info>,
+    pub ucp_pda_account: &'a AccountInfo<'info>,
+}
+
+/// Sends {amount} of collateral from the UCP to the LPCP
+pub fn send_collateral(
+    ucp_program_id: &Pubkey,
+    accounts: SendCollateralAccounts,
+    amount: u64,
+    signer: OracleSigner,
+) -> ProgramResult {
+    let instruction = Instruction {
+        program_id: *ucp_program_id,
+        accounts: vec![
+            AccountMeta::new(*accounts.authority.key, true),
+            AccountMeta::new(*accounts.ucp_collateral_token_account.key, false),
+            AccountMeta::new(*accounts.lpcp_collateral_token_account.key, false),
+            AccountMeta::new(*accounts.ucp_account.key, false),
+            AccountMeta::new_readonly(*accounts.token_program.key, false),
+            AccountMeta::new_readonly(*accounts.ucp_pda_account.key, false),
+        ],
+        data: instruction_data(SEND_COLLATERAL, amount),
+    };
+
+    signer.invoke_signed(
+        &instruction,
+        &[
+            accounts.authority.clone(),
+            accounts.ucp_collateral_token_account.clone(),
+            accounts.lpcp_collateral_token_account.clone(),
+            accounts.ucp_account.clone(),
+            accounts.token_program.clone(),
+            accounts.ucp_pda_account.clone(),
+        ],
+    )
+}
@@ -0,0 +1,249 @@
+// inside curve.rs
+//! Swap curves used to price sales into an AMM from its balances.
+//!
+//! Each AMM's curve is selected by the Oracle admin in a `CurveConfig` account, so
+//! stable-like iAssets can use a StableSwap invariant while the rest keep the
+//! constant product.
+
+use std::convert::TryFrom;
+
+use solana_program::{msg, program_error::ProgramError};
+
+use crate::{error::OracleError, math::{pow10, Rounding, U192}};
+
+/// Number of tokens in an AMM, USDI and one iAsset
+const N_COINS: u64 = 2;
+
+/// Newton's method iterations before giving up on a closer StableSwap estimate
+const MAX_ITERATIONS: usize = 256;
+
+/// Decimals of the mints of the token paid out and the token sold into an AMM
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct SwapDecimals {
+    pub out_decimals: u32,
+    pub in_decimals: u32,
+}
+
+impl SwapDecimals {
+    pub fn new(out_decimals: u32, in_decimals: u32) -> Self {
+        Self { out_decimals, in_decimals }
+    }
+}
+
+pub trait Curve {
+    /// Returns the amount paid out of {reserve_out} when {amount_in} is sold into
+    /// {reserve_in}, rounded in the given direction
+    ///
+    /// Amounts are in base units of mints with {decimals}
+    fn sell_price_rounded(&self, reserve_out: u64, reserve_in: u64, amount_in: u64, decimals: SwapDecimals, rounding: Rounding) -> Result<u64, ProgramError>;
+
+    /// Like `sell_price_rounded`, rounded down so the AMM never pays out more than its
+    /// invariant allows
+    fn sell_price(&self, reserve_out: u64, reserve_in: u64, amount_in: u64, decimals: SwapDecimals) -> Result<u64, ProgramError> {
+        self.sell_price_rounded(reserve_out, reserve_in, amount_in, decimals, Rounding::RoundDown)
+    }
+}
+
+/// Curve selected by `CurveConfig::curve_type`
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum CurveType {
+    ConstantProduct = 0,
+    StableSwap = 1,
+}
+
+impl TryFrom<u8> for CurveType {
+    type Error = ProgramError;
+
+    fn try_from(value: u8) -> Result<Self, Self::Error> {
+        match value {
+            0 => Ok(CurveType::ConstantProduct),
+            1 => Ok(CurveType::StableSwap),
+            _ => {
+                msg!("Unknown curve type {}", value);
+                Err(OracleError::InvalidCurve.into())
+            }
+        }
+    }
+}
+
+/// The x·y=k invariant, which holds whatever the mints' decimals
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct ConstantProduct;
+
+impl Curve for ConstantProduct {
+    fn sell_price_rounded(&self, reserve_out: u64, reserve_in: u64, amount_in: u64, _decimals: SwapDecimals, rounding: Rounding) -> Result<u64, ProgramError> {
+        Ok(constant_product_sell_price(reserve_out, reserve_in, amount_in, rounding))
+    }
+}
+
+/// The two-token StableSwap invariant A·n^n·(x+y) + D = A·D·n^n + D^(n+1)/(n^n·x·y)
+///
+/// Trades near the balanced point with little slippage; a larger {amplification}
+/// flattens the curve further, and the curve tends to the constant product as it
+/// approaches zero
+///
+/// Balances are scaled to the larger of the two mints' decimals before solving, so
+/// the balanced point is one whole USDI per whole iAsset
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct StableSwap {
+    amplification: u64,
+}
+
+impl StableSwap {
+    pub fn new(amplification: u64) -> Result<Self, ProgramError> {
+        if amplification == 0 {
+            msg!("StableSwap amplification must be positive");
+            return Err(OracleError::InvalidCurve.into());
+        }
+        Ok(Self { amplification })
+    }
+
+    pub fn amplification(&self) -> u64 {
+        self.amplification
+    }
+
+    fn leverage(&self) -> U192 {
+        U192::from(self.amplification) * U192::from(N_COINS)
+    }
+
+    /// Solves the invariant for D given balances {x} and {y}, both non-zero
+    fn compute_d(&self, x: U192, y: U192) -> Result<U192, ProgramError> {
+        let n = U192::from(N_COINS);
+        let leverage = self.leverage();
+        let sum = x + y;
+
+        let mut d = sum;
+        for _ in 0..MAX_ITERATIONS {
+            //D^(n+1)/(n^n·x·y), divided step by step to stay within 192 bits
+            let d_product = div(mul(div(mul(d, d)?, mul(x, n)?)?, d)?, mul(y, n)?)?;
+            let numerator = mul(add(mul(leverage, sum)?, mul(d_product, n)?)?, d)?;
+            let denominator = add(mul(leverage - 1, d)?, mul(n + 1, d_product)?)?;
+            let d_previous = d;
+            d = div(numerator, denominator)?;
+            if abs_diff(d, d_previous) <= U192::one() {
+                break;
+            }
+        }
+        Ok(d)
+    }
+
+    /// Solves the invariant for the other balance once one balance is {x}
+    fn compute_y(&self, x: U192, d: U192) -> Result<U192, ProgramError> {
+        let n = U192::from(N_COINS);
+        let leverage = self.leverage();
+        let c = div(mul(div(mul(d, d)?, mul(x, n)?)?, d)?, mul(leverage, n)?)?;
+        let b = add(x, div(d, leverage)?)?;
+
+        let mut y = d;
+        for _ in 0..MAX_ITERATIONS {
+            let y_previous = y;
+            let denominator = sub(add(mul(y, n)?, b)?, d)?;
+            y = div(add(mul(y, y)?, c)?, denominator)?;
+            if abs_diff(y, y_previous) <= U192::one() {
+                break;
+            }
+        }
+        Ok(y)
+    }
+}
+
+impl Curve for StableSwap {
+    /// An empty side of the pool pays nothing, since D is undefined without both tokens
+    fn sell_price_rounded(&self, reserve_out: u64, reserve_in: u64, amount_in: u64, decimals: SwapDecimals, rounding: Rounding) -> Result<u64, ProgramError> {
+        if reserve_out == 0 || reserve_in == 0 || amount_in == 0 {
+            return Ok(0);
+        }
+        let precision = decimals.out_decimals.max(decimals.in_decimals);
+        let out_scale = pow10(precision - decimals.out_decimals)?;
+        let in_scale = pow10(precision - decimals.in_decimals)?;
+        let reserve_out = mul(U192::from(reserve_out), out_scale)?;
+        let reserve_in = mul(U192::from(reserve_in), in_scale)?;
+        let amount_in = mul(U192::from(amount_in), in_scale)?;
+
+        let d = self.compute_d(reserve_in, reserve_out)?;
+        let reserve_out_after = self.compute_y(add(reserve_in, amount_in)?, d)?;
+
+        //Newton's method only lands within one unit of the balance, so that unit stays in
+        //the AMM when rounding down and is paid out when rounding up
+        let reserve_out_after = match rounding {
+            Rounding::RoundDown => reserve_out_after + 1,
+            Rounding::RoundUp => reserve_out_after.saturating_sub(U192::one()),
+        };
+        if reserve_out_after >= reserve_out {
+            return Ok(0);
+        }
+
+        //Back to the paid out mint's base units, rounded the same way
+        let amount_out = reserve_out - reserve_out_after;
+        let amount_out = match rounding {
+            Rounding::RoundDown => amount_out / out_scale,
+            Rounding::RoundUp => div(add(amount_out, out_scale - 1)?, out_scale)?,
+        };
+        Ok(amount_out.as_u64())
+    }
+}
+
+/// Returns the USDI paid out by a constant product (x·y=k) AMM holding {usdi_amm_amount}
+/// and {iasset_amm_amount} when {iasset_purchase_amount} iAsset is sold into it
+///
+/// The exact output is x·n/(y+n), rounded down so the AMM never pays out more than
+/// the invariant allows
+pub fn calc_sell_price_from_num_iasset(
+    usdi_amm_amount: u64,
+    iasset_amm_amount: u64,
+    iasset_purchase_amount: u64,
+) -> u64 {
+    constant_product_sell_price(usdi_amm_amount, iasset_amm_amount, iasset_purchase_amount, Rounding::RoundDown)
+}
+
+/// x·n/(y+n) for a constant product AMM, rounded in the given direction
+fn constant_product_sell_price(
+    reserve_out: u64,
+    reserve_in: u64,
+    amount_in: u64,
+    rounding: Rounding,
+) -> u64 {
+    let reserve_in_after = reserve_in as u128 + amount_in as u128;
+    if reserve_in_after == 0 {
+        return 0;
+    }
+
+    //Cannot exceed reserve_out, since amount_in <= reserve_in_after
+    let numerator = reserve_out as u128 * amount_in as u128;
+    match rounding {
+        Rounding::RoundDown => (numerator / reserve_in_after) as u64,
+        Rounding::RoundUp => numerator.div_ceil(reserve_in_after) as u64,
+    }
+}
+
+/// Builds the curve for an AMM's {curve_type} and {amplification}
+pub fn curve_from_config(curve_type: u8, amplification: u64) -> Result<Box<dyn Curve>, ProgramError> {
+    match CurveType::try_from(curve_type)? {
+        CurveType::ConstantProduct => Ok(Box::new(ConstantProduct)),
+        CurveType::StableSwap => Ok(Box::new(StableSwap::new(amplification)?)),
+    }
+}
+
+fn abs_diff(a: U192, b: U192) -> U192 {
+    if a > b {
+        a - b
+    } else {
+        b - a
+    }
+}
+
+fn add(a: U192, b: U192) -> Result<U192, ProgramError> {
+    a.checked_add(b).ok_or_else(|| OracleError::MathOverflow.into())
+}
+
+fn sub(a: U192, b: U192) -> Result<U192, ProgramError> {
+    a.checked_sub(b).ok_or_else(|| OracleError::MathUnderflow.into())
+}
+
+fn mul(a: U192, b: U192) -> Result<U192, ProgramError> {
+    a.checked_mul(b).ok_or_else(|| OracleError::MathOverflow.into())
+}
+
+fn div(a: U192, b: U192) -> Result<U192, ProgramError> {
+    a.checked_div(b).ok_or_else(|| OracleError::MathOverflow.into())
+}
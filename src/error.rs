@@ -20,6 +20,63 @@ pub enum OracleError {
     /// Mismatched PDA Accounts For AMMs
     #[error("MismatchedPDAAccountsForAMMs")]
     MismatchedPDAAccountsForAMMs,
+
+    /// Instruction data ended before every field was read
+    #[error("Instruction Data Too Short")]
+    InstructionDataTooShort,
+
+    /// Instruction data has bytes left over after the last field
+    #[error("Trailing Instruction Data")]
+    TrailingInstructionData,
+
+    /// Trade output was below the user's minimum
+    #[error("Slippage Exceeded")]
+    SlippageExceeded,
+
+    /// Trade landed after its expiry slot
+    #[error("Trade Expired")]
+    TradeExpired,
+
+    /// AMM price is further from the oracle price than the configured band
+    #[error("Price Outside Oracle Band")]
+    PriceOutsideOracleBand,
+
+    /// Oracle price is not trading, not positive, or cannot be normalized
+    #[error("Invalid Oracle Price")]
+    InvalidOraclePrice,
+
+    /// Instruction is paused by the Oracle admin
+    #[error("Paused")]
+    Paused,
+
+    /// Signer is not the Oracle admin
+    #[error("Invalid Admin")]
+    InvalidAdmin,
+
+    /// Correction ran again before the minimum interval without a large enough price move
+    #[error("Correction Too Frequent")]
+    CorrectionTooFrequent,
+
+    /// Integer arithmetic overflowed
+    #[error("Math Overflow")]
+    MathOverflow,
+
+    /// Integer arithmetic underflowed
+    #[error("Math Underflow")]
+    MathUnderflow,
+
+    /// Amount to convert to a token amount is negative
+    #[error("Negative Amount")]
+    NegativeAmount,
+
+    /// AMM curve type is unknown, its parameters are invalid, or its curve config
+    /// belongs to another Oracle or AMM
+    #[error("Invalid Curve")]
+    InvalidCurve,
+
+    /// The same AMM is passed more than once to an instruction that expects distinct AMMs
+    #[error("Duplicate AMM")]
+    DuplicateAmm,
 }
 
 impl From<OracleError> for ProgramError {
@@ -1,34 +1,75 @@
 // inside instruction.rs
 use solana_program::program_error::ProgramError;
-use solana_program::pubkey::Pubkey;
+use solana_program::pubkey::{Pubkey, PUBKEY_BYTES};
 use solana_program::msg;
 use std::convert::TryInto;
-use crate::error::OracleError::InvalidInstruction;
+use std::mem::size_of;
+use crate::error::OracleError::{InstructionDataTooShort, InvalidInstruction, TrailingInstructionData};
 
+#[derive(Clone, Debug, PartialEq)]
 pub enum OracleInstruction {
 
     /// Initializes the Oracle
     ///
+    /// {oracle_band_bps} is the largest deviation, in basis points, between an AMM's
+    /// price and the oracle price at which trades will still be routed, with zero
+    /// disabling the band, and {trade_fee_bps} is the share, in basis points, of the
+    /// USDI side of each trade skimmed into the fee token account
+    ///
+    /// The keeper running CollateralCorrection is paid {keeper_reward} from the fee
+    /// token account, at most once every {keeper_reward_interval_slots} slots and
+    /// only for a correction that minted or burned iAsset or moved collateral
+    ///
+    /// CollateralCorrection may correct each AMM at most once every
+    /// {min_correction_interval_slots} slots, unless the oracle price has moved by more
+    /// than {correction_override_bps} basis points since that AMM's last correction; an
+    /// override of zero is disabled
+    ///
+    /// Each run mints or burns at most {max_correction_amount} iAsset per AMM, and at
+    /// most {max_correction_bps} basis points of the AMM's iAsset balance; a cap of
+    /// zero is not applied
+    ///
+    /// The fee token account is handed to the Oracle PDA derived from
+    /// `[b"incept", oracle account key]`, so each Oracle has its own authority
+    ///
+    /// Every later instruction only accepts the Pyth product and price accounts
+    /// passed here, and only while they are owned by {pyth_program_id}
     ///
     /// Accounts expected:
     ///
-    /// 0. `[signer]` The Initializer Account
+    /// 0. `[signer]` The Initializer Account, which becomes the Oracle admin
     /// 1. `[writable]` Temporary fee token account that should be created prior to this instruction and owned by The Initializer Account
     /// 2. `[writable]` The Oracle account holding the Oracle info
     /// 3. `[]` The rent sysvar
     /// 4. `[]` The token program
+    /// 5. `[]` The Pyth product account
+    /// 6. `[]` The Pyth price account
     InitOracle {
         amm_program_id: Pubkey,
         lpcp_program_id: Pubkey,
         ucp_program_id: Pubkey,
+        pyth_program_id: Pubkey,
+        oracle_band_bps: u64,
+        trade_fee_bps: u64,
+        keeper_reward: u64,
+        keeper_reward_interval_slots: u64,
+        min_correction_interval_slots: u64,
+        correction_override_bps: u64,
+        max_correction_amount: u64,
+        max_correction_bps: u64,
     },
 
     /// Allows user to trade {amount (in USDI)} of USDI in exchange for iAsset
-    ///  
+    ///
+    /// The trade fee is charged on the USDI paid into the AMM, as a separate transfer
+    /// from the user's USDI on top of {amount}
+    ///
+    /// Fails if the user receives less than {min_amount_out} iAsset,
+    /// or if {expiry_slot} is set and the current slot is past it
     ///
     /// Accounts expected:
     ///
-    /// 0. `[signer]` Oracle token account that should be created prior to this instruction and owned by The Oracle Program
+    /// 0. `[writable]` Oracle fee token account, owned by the Oracle PDA
     /// 1. `[writable]` The Oracle account holding the Oracle info
     /// 2. `[signer]` The User's account
     /// 3. `[writable]` The USDI token account owned by the user's account
@@ -39,20 +80,36 @@ pub enum OracleInstruction {
     /// 8. `[]` The token program
     /// 9. `[]` The AMM PDA account
     /// 10. `[writable]` The UCP account holding the UCP info
-    /// 11. `[signer]` UCP token account that should be created prior to this instruction and owned by The UCP Program
+    /// 11. `[writable]` UCP token account that should be created prior to this instruction and owned by The UCP Program
     /// 12. `[writable]` LPCP token account that should be created prior to this instruction and owned by The LPCP Program
     /// 13. `[]` The UCP PDA account
+    /// 14. `[writable]` The Oracle PDA account, which signs the AMM and UCP calls
+    ///
+    /// Unless {oracle_band_bps} is zero, to refuse the trade if the AMM price is outside the oracle band:
+    ///
+    /// 15. `[]` The Pyth product account bound to the Oracle at InitOracle
+    /// 16. `[]` The Pyth price account bound to the Oracle at InitOracle
+    /// 17. `[]` The USDI mint account
+    /// 18. `[]` The AMM iAsset mint account
+    ///
+    /// Followed by the AMM and UCP programs, so they can be invoked
     TradeUSDI {
         amount: u64,
+        min_amount_out: u64,
+        expiry_slot: Option<u64>,
     },
 
 
-    /// Allows user to trade {amount (in USDI)} of USDI in exchange for iAsset
-    ///  
+    /// Allows user to trade {amount (in iAsset)} of iAsset in exchange for USDI
+    ///
+    /// The trade fee is taken from the USDI received from the AMM
+    ///
+    /// Fails if the user receives less than {min_amount_out} USDI after the fee,
+    /// or if {expiry_slot} is set and the current slot is past it
     ///
     /// Accounts expected:
     ///
-    /// 0. `[signer]` Oracle token account that should be created prior to this instruction and owned by The Oracle Program
+    /// 0. `[writable]` Oracle fee token account, owned by the Oracle PDA
     /// 1. `[writable]` The Oracle account holding the Oracle info
     /// 2. `[signer]` The User's account
     /// 3. `[writable]` The USDI token account owned by the user's account
@@ -63,83 +120,485 @@ pub enum OracleInstruction {
     /// 8. `[]` The token program
     /// 9. `[]` The AMM PDA account
     /// 10. `[writable]` The UCP account holding the UCP info
-    /// 11. `[signer]` UCP token account that should be created prior to this instruction and owned by The UCP Program
+    /// 11. `[writable]` UCP token account that should be created prior to this instruction and owned by The UCP Program
     /// 12. `[writable]` LPCP token account that should be created prior to this instruction and owned by The LPCP Program
     /// 13. `[]` The UCP PDA account
+    /// 14. `[writable]` The Oracle PDA account, which signs the AMM and UCP calls
+    ///
+    /// Unless {oracle_band_bps} is zero, to refuse the trade if the AMM price is outside the oracle band:
+    ///
+    /// 15. `[]` The Pyth product account bound to the Oracle at InitOracle
+    /// 16. `[]` The Pyth price account bound to the Oracle at InitOracle
+    /// 17. `[]` The USDI mint account
+    /// 18. `[]` The AMM iAsset mint account
+    ///
+    /// Followed by the AMM and UCP programs, so they can be invoked
     TradeiAsset {
         amount: u64,
+        min_amount_out: u64,
+        expiry_slot: Option<u64>,
     },
 
 
     /// Allows user to withdraw USDI and claim collateral
-    ///  
+    ///
+    /// Anyone may run the correction: the Oracle PDA is the only signer of the
+    /// AMM, UCP and LPCP calls
+    ///
+    /// USDI and iAsset amounts are scaled by their mints' decimals before being
+    /// compared with the oracle price, so iAssets with different decimals are
+    /// corrected to the same LPR, measured with each AMM's curve
+    ///
+    /// {num_amms} must be at least one, and each AMM may only be passed once. Only an
+    /// AMM whose iAsset was minted or burned has its correction recorded in its curve
+    /// config, so a correction that leaves an AMM unchanged does not hold back the next
+    /// one
     ///
     /// Accounts expected:
     ///
-    /// 0. `[signer]` Oracle token account that should be created prior to this instruction and owned by The Oracle Program
-    /// 1. `[writable]` The Oracle account holding the Oracle info
-    /// 2. `[]` The token program
-    /// 3. `[]` The AMM PDA account
-    /// 4. `[writable]` LPCP token account that should be created prior to this instruction and owned by The LPCP Program
-    /// 5. `[signer]` UCP token account that should be created prior to this instruction and owned by The UCP Program
-    /// 6. `[writable]` The LPCP account holding the LPCP info
-    /// 7. `[writable]` The UCP account holding the UCP info
-    /// 8. `[]` The LPCP PDA account
-    /// 9. `[]` The UCP PDA account
+    /// 0. `[]` The Pyth product account
+    /// 1. `[]` The Pyth price account
+    /// 2. `[writable]` Oracle fee token account, owned by the Oracle PDA
+    /// 3. `[writable]` The Oracle account holding the Oracle info
+    /// 4. `[]` The token program
+    /// 5. `[]` The AMM PDA account
+    /// 6. `[writable]` LPCP token account that should be created prior to this instruction and owned by The LPCP Program
+    /// 7. `[writable]` UCP token account that should be created prior to this instruction and owned by The UCP Program
+    /// 8. `[writable]` The LPCP account holding the LPCP info
+    /// 9. `[writable]` The UCP account holding the UCP info
+    /// 10. `[]` The LPCP PDA account
+    /// 11. `[]` The UCP PDA account
+    /// 12. `[signer]` The keeper account running the correction
+    /// 13. `[writable]` The token account receiving the keeper reward
+    /// 14. `[writable]` The Oracle PDA account, which signs the AMM, UCP and LPCP calls
+    /// 15. `[]` The USDI mint account
+    ///
     /// FOR EACH AMM IN INCEPT ECOSYSTEM
-    /// 10 + 3i. `[writable]` The AMM account holding the AMM info
-    /// 11 + 3i. `[writable]` The AMM PDA's USDI token account
-    /// 12 + 3i. `[writable]` The AMM PDA's iAsset token account
-    /// 13 + 3i. `[writable]` The AMM iAsset mint account
+    /// 16 + 5i. `[writable]` The AMM account holding the AMM info
+    /// 17 + 5i. `[writable]` The AMM PDA's USDI token account
+    /// 18 + 5i. `[writable]` The AMM PDA's iAsset token account
+    /// 19 + 5i. `[writable]` The AMM iAsset mint account
+    /// 20 + 5i. `[writable]` The AMM's curve config account, see SetCurve
+    ///
+    /// Followed by the AMM, UCP and LPCP programs, so they can be invoked
     CollateralCorrection {
         num_amms: u64,
     },
+
+    /// Allows user to trade {amount} of one iAsset for another in a single call,
+    /// selling it for USDI through the first AMM and buying through the second, which
+    /// must be a different AMM
+    ///
+    /// A single trade fee is taken from the USDI received from the first AMM,
+    /// before the rest is spent on the second
+    ///
+    /// Fails if the user receives less than {min_amount_out} of the bought iAsset,
+    /// or if {expiry_slot} is set and the current slot is past it
+    ///
+    /// Accounts expected:
+    ///
+    /// 0. `[writable]` Oracle fee token account, owned by the Oracle PDA
+    /// 1. `[writable]` The Oracle account holding the Oracle info
+    /// 2. `[signer]` The User's account
+    /// 3. `[writable]` The USDI token account owned by the user's account
+    /// 4. `[writable]` The token account owned by the user's account for the iAsset being sold
+    /// 5. `[writable]` The token account owned by the user's account for the iAsset being bought
+    /// 6. `[]` The token program
+    /// 7. `[writable]` The UCP account holding the UCP info
+    /// 8. `[writable]` UCP token account that should be created prior to this instruction and owned by The UCP Program
+    /// 9. `[writable]` LPCP token account that should be created prior to this instruction and owned by The LPCP Program
+    /// 10. `[]` The UCP PDA account
+    /// 11. `[writable]` The Oracle PDA account, which signs the AMM and UCP calls
+    ///
+    /// FOR THE SELLING AMM (i = 0) AND THE BUYING AMM (i = 1)
+    /// 12 + 4i. `[writable]` The AMM PDA's USDI token account
+    /// 13 + 4i. `[writable]` The AMM PDA's iAsset token account
+    /// 14 + 4i. `[writable]` The AMM account holding the AMM info
+    /// 15 + 4i. `[]` The AMM PDA account
+    ///
+    /// Unless {oracle_band_bps} is zero, to refuse either leg if its AMM price is outside the oracle band:
+    ///
+    /// 20. `[]` The Pyth product account bound to the Oracle at InitOracle
+    /// 21. `[]` The Pyth price account bound to the Oracle at InitOracle
+    /// 22. `[]` The USDI mint account
+    /// 23. `[]` The iAsset mint account of the selling AMM
+    /// 24. `[]` The iAsset mint account of the buying AMM
+    ///
+    /// Followed by the AMM and UCP programs, so they can be invoked
+    TradeRoute {
+        amount: u64,
+        min_amount_out: u64,
+        expiry_slot: Option<u64>,
+    },
+
+    /// Quotes a trade of {amount} against an AMM without executing it, USDI for
+    /// iAsset if {usdi_in} is set and iAsset for USDI otherwise
+    ///
+    /// The expected output, spread, oracle fair value and trade fee are returned
+    /// as a packed `TradeQuote` in the transaction's return data; the
+    /// output is priced with the AMM's curve, see SetCurve
+    ///
+    /// The spread is the output lost to the curve against the AMM's current
+    /// price, and the fair value is {amount} converted at the oracle price,
+    /// scaled by the USDI and iAsset mints' decimals
+    ///
+    /// The trade fee is in USDI, charged as TradeUSDI and TradeiAsset charge it:
+    /// on top of {amount} when {usdi_in} is set, and otherwise already taken out
+    /// of the expected output
+    ///
+    /// Accounts expected:
+    ///
+    /// 0. `[]` The Oracle account holding the Oracle info
+    /// 1. `[]` The Pyth product account
+    /// 2. `[]` The Pyth price account
+    /// 3. `[]` The AMM account holding the AMM info
+    /// 4. `[]` The AMM PDA's USDI token account
+    /// 5. `[]` The AMM PDA's iAsset token account
+    /// 6. `[]` The AMM's curve config account
+    /// 7. `[]` The USDI mint account
+    /// 8. `[]` The AMM iAsset mint account
+    Quote {
+        amount: u64,
+        usdi_in: bool,
+    },
+
+    /// Validates the Oracle's Pyth product and price accounts and returns the price
+    ///
+    /// The price and confidence, normalized to `OraclePrice::DECIMALS` decimals,
+    /// and the publish slot are returned as a packed `OraclePrice` in the
+    /// transaction's return data
+    ///
+    /// Accounts expected:
+    ///
+    /// 0. `[]` The Oracle account holding the Oracle info
+    /// 1. `[]` The Pyth product account bound to the Oracle at InitOracle
+    /// 2. `[]` The Pyth price account bound to the Oracle at InitOracle
+    GetPrice,
+
+    /// Replaces the Oracle's paused flags, a bitfield of `Oracle::PAUSE_TRADING`,
+    /// `Oracle::PAUSE_CORRECTION`, `Oracle::PAUSE_QUOTE`, `Oracle::PAUSE_GET_PRICE`
+    /// and `Oracle::PAUSE_WITHDRAW_FEES`
+    ///
+    /// Accounts expected:
+    ///
+    /// 0. `[signer]` The Oracle admin account
+    /// 1. `[writable]` The Oracle account holding the Oracle info
+    SetPause {
+        paused_flags: u8,
+    },
+
+    /// Allows the Oracle admin to withdraw {amount} of collected trade fees
+    ///
+    /// Accounts expected:
+    ///
+    /// 0. `[signer]` The Oracle admin account
+    /// 1. `[]` The Oracle account holding the Oracle info
+    /// 2. `[writable]` The Oracle fee token account
+    /// 3. `[writable]` The token account receiving the fees
+    /// 4. `[]` The token program
+    /// 5. `[]` The Oracle PDA account
+    WithdrawFees {
+        amount: u64,
+    },
+
+    /// Allows the Oracle admin to select the curve an AMM is priced with by
+    /// Quote and CollateralCorrection, see `curve::CurveType`
+    ///
+    /// {amplification} is only used by the StableSwap curve. The curve config
+    /// account may be updated again later, but only for the same Oracle and AMM,
+    /// and keeps the AMM's last correction recorded by CollateralCorrection
+    ///
+    /// Accounts expected:
+    ///
+    /// 0. `[signer]` The Oracle admin account
+    /// 1. `[]` The Oracle account holding the Oracle info
+    /// 2. `[writable]` The curve config account, created prior to this instruction and owned by the Oracle program
+    /// 3. `[]` The rent sysvar
+    /// 4. `[]` The AMM account holding the AMM info
+    SetCurve {
+        curve_type: u8,
+        amplification: u64,
+    },
 }
 
 impl OracleInstruction {
 
+    /// Unpacks a byte buffer into an [OracleInstruction](enum.OracleInstruction.html).
+    ///
+    /// Every field must be present and no bytes may follow the last field.
     pub fn unpack(input: &[u8]) -> Result<Self, ProgramError> {
         let (tag, rest) = input.split_first().ok_or(InvalidInstruction)?;
 
-        Ok(match tag {
-            0 => Self::InitOracle {
-                amm_program_id: Self::unpack_pubkey(rest, 0)?,
-                lpcp_program_id: Self::unpack_pubkey(rest, 32)?,
-                ucp_program_id: Self::unpack_pubkey(rest, 64)?,
-            },
-            1 => Self::TradeUSDI {
-                amount: Self::unpack_amount(rest)?,
-            },
-            2 => Self::TradeiAsset {
-                amount: Self::unpack_amount(rest)?,
-            },
-            3 => Self::CollateralCorrection {
-                num_amms: Self::unpack_amount(rest)?,
-            },
+        let (instruction, rest) = match tag {
+            0 => {
+                let (amm_program_id, rest) = Self::unpack_pubkey(rest, "amm_program_id")?;
+                let (lpcp_program_id, rest) = Self::unpack_pubkey(rest, "lpcp_program_id")?;
+                let (ucp_program_id, rest) = Self::unpack_pubkey(rest, "ucp_program_id")?;
+                let (pyth_program_id, rest) = Self::unpack_pubkey(rest, "pyth_program_id")?;
+                let (oracle_band_bps, rest) = Self::unpack_u64(rest, "oracle_band_bps")?;
+                let (trade_fee_bps, rest) = Self::unpack_u64(rest, "trade_fee_bps")?;
+                let (keeper_reward, rest) = Self::unpack_u64(rest, "keeper_reward")?;
+                let (keeper_reward_interval_slots, rest) = Self::unpack_u64(rest, "keeper_reward_interval_slots")?;
+                let (min_correction_interval_slots, rest) = Self::unpack_u64(rest, "min_correction_interval_slots")?;
+                let (correction_override_bps, rest) = Self::unpack_u64(rest, "correction_override_bps")?;
+                let (max_correction_amount, rest) = Self::unpack_u64(rest, "max_correction_amount")?;
+                let (max_correction_bps, rest) = Self::unpack_u64(rest, "max_correction_bps")?;
+                (
+                    Self::InitOracle {
+                        amm_program_id,
+                        lpcp_program_id,
+                        ucp_program_id,
+                        pyth_program_id,
+                        oracle_band_bps,
+                        trade_fee_bps,
+                        keeper_reward,
+                        keeper_reward_interval_slots,
+                        min_correction_interval_slots,
+                        correction_override_bps,
+                        max_correction_amount,
+                        max_correction_bps,
+                    },
+                    rest,
+                )
+            }
+            1 => {
+                let (amount, rest) = Self::unpack_u64(rest, "amount")?;
+                let (min_amount_out, rest) = Self::unpack_u64(rest, "min_amount_out")?;
+                let (expiry_slot, rest) = Self::unpack_u64_option(rest, "expiry_slot")?;
+                (
+                    Self::TradeUSDI {
+                        amount,
+                        min_amount_out,
+                        expiry_slot,
+                    },
+                    rest,
+                )
+            }
+            2 => {
+                let (amount, rest) = Self::unpack_u64(rest, "amount")?;
+                let (min_amount_out, rest) = Self::unpack_u64(rest, "min_amount_out")?;
+                let (expiry_slot, rest) = Self::unpack_u64_option(rest, "expiry_slot")?;
+                (
+                    Self::TradeiAsset {
+                        amount,
+                        min_amount_out,
+                        expiry_slot,
+                    },
+                    rest,
+                )
+            }
+            3 => {
+                let (num_amms, rest) = Self::unpack_u64(rest, "num_amms")?;
+                (Self::CollateralCorrection { num_amms }, rest)
+            }
+            4 => {
+                let (amount, rest) = Self::unpack_u64(rest, "amount")?;
+                let (min_amount_out, rest) = Self::unpack_u64(rest, "min_amount_out")?;
+                let (expiry_slot, rest) = Self::unpack_u64_option(rest, "expiry_slot")?;
+                (
+                    Self::TradeRoute {
+                        amount,
+                        min_amount_out,
+                        expiry_slot,
+                    },
+                    rest,
+                )
+            }
+            5 => {
+                let (amount, rest) = Self::unpack_u64(rest, "amount")?;
+                let (usdi_in, rest) = Self::unpack_bool(rest, "usdi_in")?;
+                (Self::Quote { amount, usdi_in }, rest)
+            }
+            6 => (Self::GetPrice, rest),
+            7 => {
+                let (paused_flags, rest) = Self::unpack_u8(rest, "paused_flags")?;
+                (Self::SetPause { paused_flags }, rest)
+            }
+            8 => {
+                let (amount, rest) = Self::unpack_u64(rest, "amount")?;
+                (Self::WithdrawFees { amount }, rest)
+            }
+            9 => {
+                let (curve_type, rest) = Self::unpack_u8(rest, "curve_type")?;
+                let (amplification, rest) = Self::unpack_u64(rest, "amplification")?;
+                (Self::SetCurve { curve_type, amplification }, rest)
+            }
             _ => return Err(InvalidInstruction.into()),
-        })
+        };
+
+        if !rest.is_empty() {
+            msg!("Instruction has {} unexpected trailing bytes", rest.len());
+            return Err(TrailingInstructionData.into());
+        }
+
+        Ok(instruction)
     }
 
-    fn unpack_pubkey(input: &[u8], start: u8) -> Result<Pubkey, ProgramError> {
-        let mut pubkey: Option<Pubkey> = None;
-        let pubkey_array = input.get(usize::from(start)..usize::from(start)+32).and_then(|slice| slice.try_into().ok());
-        match pubkey_array {
-            Some(array) => matches!(Pubkey::new_from_array(array), pubkey),
-            None => false,
-        };
-        match pubkey {
-            Some(pubk) => return Ok(pubk),
-            None => return Err(InvalidInstruction.into()),
+    /// Packs an [OracleInstruction](enum.OracleInstruction.html) into a byte buffer.
+    pub fn pack(&self) -> Vec<u8> {
+        let mut buf = Vec::with_capacity(size_of::<Self>());
+        match self {
+            Self::InitOracle {
+                amm_program_id,
+                lpcp_program_id,
+                ucp_program_id,
+                pyth_program_id,
+                oracle_band_bps,
+                trade_fee_bps,
+                keeper_reward,
+                keeper_reward_interval_slots,
+                min_correction_interval_slots,
+                correction_override_bps,
+                max_correction_amount,
+                max_correction_bps,
+            } => {
+                buf.push(0);
+                buf.extend_from_slice(amm_program_id.as_ref());
+                buf.extend_from_slice(lpcp_program_id.as_ref());
+                buf.extend_from_slice(ucp_program_id.as_ref());
+                buf.extend_from_slice(pyth_program_id.as_ref());
+                buf.extend_from_slice(&oracle_band_bps.to_le_bytes());
+                buf.extend_from_slice(&trade_fee_bps.to_le_bytes());
+                buf.extend_from_slice(&keeper_reward.to_le_bytes());
+                buf.extend_from_slice(&keeper_reward_interval_slots.to_le_bytes());
+                buf.extend_from_slice(&min_correction_interval_slots.to_le_bytes());
+                buf.extend_from_slice(&correction_override_bps.to_le_bytes());
+                buf.extend_from_slice(&max_correction_amount.to_le_bytes());
+                buf.extend_from_slice(&max_correction_bps.to_le_bytes());
+            }
+            Self::TradeUSDI {
+                amount,
+                min_amount_out,
+                expiry_slot,
+            } => {
+                buf.push(1);
+                buf.extend_from_slice(&amount.to_le_bytes());
+                buf.extend_from_slice(&min_amount_out.to_le_bytes());
+                Self::pack_u64_option(expiry_slot, &mut buf);
+            }
+            Self::TradeiAsset {
+                amount,
+                min_amount_out,
+                expiry_slot,
+            } => {
+                buf.push(2);
+                buf.extend_from_slice(&amount.to_le_bytes());
+                buf.extend_from_slice(&min_amount_out.to_le_bytes());
+                Self::pack_u64_option(expiry_slot, &mut buf);
+            }
+            Self::CollateralCorrection { num_amms } => {
+                buf.push(3);
+                buf.extend_from_slice(&num_amms.to_le_bytes());
+            }
+            Self::TradeRoute {
+                amount,
+                min_amount_out,
+                expiry_slot,
+            } => {
+                buf.push(4);
+                buf.extend_from_slice(&amount.to_le_bytes());
+                buf.extend_from_slice(&min_amount_out.to_le_bytes());
+                Self::pack_u64_option(expiry_slot, &mut buf);
+            }
+            Self::Quote { amount, usdi_in } => {
+                buf.push(5);
+                buf.extend_from_slice(&amount.to_le_bytes());
+                buf.push(*usdi_in as u8);
+            }
+            Self::GetPrice => buf.push(6),
+            Self::SetPause { paused_flags } => {
+                buf.push(7);
+                buf.push(*paused_flags);
+            }
+            Self::WithdrawFees { amount } => {
+                buf.push(8);
+                buf.extend_from_slice(&amount.to_le_bytes());
+            }
+            Self::SetCurve { curve_type, amplification } => {
+                buf.push(9);
+                buf.push(*curve_type);
+                buf.extend_from_slice(&amplification.to_le_bytes());
+            }
         };
+        buf
+    }
+
+    fn unpack_pubkey<'a>(input: &'a [u8], field: &str) -> Result<(Pubkey, &'a [u8]), ProgramError> {
+        if input.len() < PUBKEY_BYTES {
+            msg!("Instruction data too short for {}", field);
+            return Err(InstructionDataTooShort.into());
+        }
+        let (key, rest) = input.split_at(PUBKEY_BYTES);
+        let pubkey = key
+            .try_into()
+            .map(Pubkey::new_from_array)
+            .map_err(|_| InstructionDataTooShort)?;
+        Ok((pubkey, rest))
+    }
+
+    fn unpack_u8<'a>(input: &'a [u8], field: &str) -> Result<(u8, &'a [u8]), ProgramError> {
+        match input.split_first() {
+            Some((&value, rest)) => Ok((value, rest)),
+            None => {
+                msg!("Instruction data too short for {}", field);
+                Err(InstructionDataTooShort.into())
+            }
+        }
+    }
+
+    fn unpack_bool<'a>(input: &'a [u8], field: &str) -> Result<(bool, &'a [u8]), ProgramError> {
+        match input.split_first() {
+            Some((&0, rest)) => Ok((false, rest)),
+            Some((&1, rest)) => Ok((true, rest)),
+            Some(_) => {
+                msg!("Invalid boolean for {}", field);
+                Err(InvalidInstruction.into())
+            }
+            None => {
+                msg!("Instruction data too short for {}", field);
+                Err(InstructionDataTooShort.into())
+            }
+        }
+    }
+
+    fn unpack_u64_option<'a>(input: &'a [u8], field: &str) -> Result<(Option<u64>, &'a [u8]), ProgramError> {
+        match input.split_first() {
+            Some((&0, rest)) => Ok((None, rest)),
+            Some((&1, rest)) => {
+                let (value, rest) = Self::unpack_u64(rest, field)?;
+                Ok((Some(value), rest))
+            }
+            Some(_) => {
+                msg!("Invalid option tag for {}", field);
+                Err(InvalidInstruction.into())
+            }
+            None => {
+                msg!("Instruction data too short for {}", field);
+                Err(InstructionDataTooShort.into())
+            }
+        }
+    }
 
+    fn pack_u64_option(value: &Option<u64>, buf: &mut Vec<u8>) {
+        match value {
+            Some(value) => {
+                buf.push(1);
+                buf.extend_from_slice(&value.to_le_bytes());
+            }
+            None => buf.push(0),
+        }
     }
 
-    fn unpack_amount(input: &[u8]) -> Result<u64, ProgramError> {
-        let amount = input
-            .get(0..8)
-            .and_then(|slice| slice.try_into().ok())
+    fn unpack_u64<'a>(input: &'a [u8], field: &str) -> Result<(u64, &'a [u8]), ProgramError> {
+        if input.len() < 8 {
+            msg!("Instruction data too short for {}", field);
+            return Err(InstructionDataTooShort.into());
+        }
+        let (value, rest) = input.split_at(8);
+        let value = value
+            .try_into()
             .map(u64::from_le_bytes)
-            .ok_or(InvalidInstruction)?;
-        Ok(amount)
+            .map_err(|_| InstructionDataTooShort)?;
+        Ok((value, rest))
     }
 }
@@ -1,7 +1,13 @@
+pub mod cpi;
+pub mod curve;
 pub mod instruction;
+pub mod math;
 pub mod error;
 pub mod processor;
 pub mod states;
 
+#[cfg(not(target_os = "solana"))]
+pub mod simulator;
+
 #[cfg(not(feature = "no-entrypoint"))]
 pub mod entrypoint;
\ No newline at end of file
@@ -0,0 +1,235 @@
+// inside math.rs
+//! Fixed-point decimal math for on-chain calculations.
+//!
+//! `Decimal` holds a value scaled by 10^18 in a 192-bit unsigned integer, so the
+//! product of two u64 token amounts with full precision still fits.
+
+#![allow(clippy::assign_op_pattern)]
+#![allow(clippy::manual_div_ceil)]
+
+use std::{fmt, str::FromStr};
+
+use solana_program::{msg, program_error::ProgramError};
+use uint::construct_uint;
+
+use crate::error::OracleError;
+
+construct_uint! {
+    pub struct U192(3);
+}
+
+/// Number of decimal places held by a `Decimal`
+pub const SCALE: u32 = 18;
+
+/// 10^SCALE, the scaled value of one
+pub const WAD: u128 = 1_000_000_000_000_000_000;
+
+/// Direction to round a `Decimal` when converting it to an integer amount
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Rounding {
+    /// Toward zero, for amounts paid out of a protocol-held pool
+    RoundDown,
+    /// Away from zero, for amounts owed to a protocol-held pool
+    RoundUp,
+}
+
+/// Unsigned fixed-point number with `SCALE` decimal places
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, PartialOrd, Ord)]
+pub struct Decimal(pub U192);
+
+impl Decimal {
+    pub fn zero() -> Self {
+        Self(U192::zero())
+    }
+
+    pub fn one() -> Self {
+        Self(U192::from(WAD))
+    }
+
+    /// Creates a decimal from a value already scaled by 10^SCALE
+    pub fn from_scaled_val(scaled_val: u128) -> Self {
+        Self(U192::from(scaled_val))
+    }
+
+    /// Creates a decimal from {value} basis points
+    pub fn from_bps(value: u64) -> Self {
+        Self(U192::from(value) * U192::from(WAD / 10_000))
+    }
+
+    /// Creates a decimal from an integer {value} with {decimals} decimal places,
+    /// e.g. a token amount and its mint's decimals
+    pub fn from_fixed(value: u64, decimals: u32) -> Result<Self, ProgramError> {
+        if decimals > SCALE {
+            let factor = pow10(decimals - SCALE)?;
+            return Ok(Self(U192::from(value) / factor));
+        }
+        let factor = pow10(SCALE - decimals)?;
+        Ok(Self(U192::from(value) * factor))
+    }
+
+    /// Scales by 10^{decimals}, the inverse of `from_fixed`, so the integer part is
+    /// the amount in a mint's base units; rounding is left to the caller
+    pub fn try_to_base_units(self, decimals: u32) -> Result<Self, ProgramError> {
+        let factor = pow10(decimals)?;
+        self.0
+            .checked_mul(factor)
+            .map(Self)
+            .ok_or_else(|| overflow("* 10^", self, Self::from(u64::from(decimals))))
+    }
+
+    pub fn is_zero(&self) -> bool {
+        self.0.is_zero()
+    }
+
+    pub fn try_add(self, rhs: Self) -> Result<Self, ProgramError> {
+        self.0
+            .checked_add(rhs.0)
+            .map(Self)
+            .ok_or_else(|| overflow("+", self, rhs))
+    }
+
+    pub fn try_sub(self, rhs: Self) -> Result<Self, ProgramError> {
+        self.0.checked_sub(rhs.0).map(Self).ok_or_else(|| {
+            msg!("Math underflow: {} - {}", self, rhs);
+            OracleError::MathUnderflow.into()
+        })
+    }
+
+    pub fn try_mul(self, rhs: Self) -> Result<Self, ProgramError> {
+        self.0
+            .checked_mul(rhs.0)
+            .map(|product| Self(product / U192::from(WAD)))
+            .ok_or_else(|| overflow("*", self, rhs))
+    }
+
+    pub fn try_div(self, rhs: Self) -> Result<Self, ProgramError> {
+        if rhs.is_zero() {
+            return Err(overflow("/", self, rhs));
+        }
+        self.0
+            .checked_mul(U192::from(WAD))
+            .map(|scaled| Self(scaled / rhs.0))
+            .ok_or_else(|| overflow("/", self, rhs))
+    }
+
+    /// Multiplies by an unscaled integer, avoiding the intermediate 10^SCALE factor
+    pub fn try_mul_u64(self, rhs: u64) -> Result<Self, ProgramError> {
+        self.0
+            .checked_mul(U192::from(rhs))
+            .map(Self)
+            .ok_or_else(|| overflow("*", self, Self::from(rhs)))
+    }
+
+    /// Divides by an unscaled integer, avoiding the intermediate 10^SCALE factor
+    pub fn try_div_u64(self, rhs: u64) -> Result<Self, ProgramError> {
+        if rhs == 0 {
+            return Err(overflow("/", self, Self::zero()));
+        }
+        Ok(Self(self.0 / U192::from(rhs)))
+    }
+
+    /// Square root, rounded down to the last decimal place
+    pub fn try_sqrt(self) -> Result<Self, ProgramError> {
+        let scaled = self
+            .0
+            .checked_mul(U192::from(WAD))
+            .ok_or_else(|| overflow("sqrt", self, Self::zero()))?;
+        Ok(Self(scaled.integer_sqrt()))
+    }
+
+    /// Converts to an integer, rounding in the given direction
+    pub fn try_to_u64(&self, rounding: Rounding) -> Result<u64, ProgramError> {
+        match rounding {
+            Rounding::RoundDown => self.try_floor_u64(),
+            Rounding::RoundUp => self.try_ceil_u64(),
+        }
+    }
+
+    /// Rounds down to the nearest integer
+    pub fn try_floor_u64(&self) -> Result<u64, ProgramError> {
+        to_u64(self.0 / U192::from(WAD), self)
+    }
+
+    /// Rounds up to the nearest integer
+    pub fn try_ceil_u64(&self) -> Result<u64, ProgramError> {
+        let wad = U192::from(WAD);
+        let ceil = self
+            .0
+            .checked_add(wad - 1)
+            .ok_or_else(|| overflow("ceil", *self, Self::zero()))?;
+        to_u64(ceil / wad, self)
+    }
+
+    /// Rounds to the nearest integer, halves away from zero
+    pub fn try_round_u64(&self) -> Result<u64, ProgramError> {
+        let wad = U192::from(WAD);
+        let rounded = self
+            .0
+            .checked_add(wad / 2)
+            .ok_or_else(|| overflow("round", *self, Self::zero()))?;
+        to_u64(rounded / wad, self)
+    }
+}
+
+impl From<u64> for Decimal {
+    fn from(value: u64) -> Self {
+        Self(U192::from(value) * U192::from(WAD))
+    }
+}
+
+impl fmt::Display for Decimal {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let wad = U192::from(WAD);
+        let fraction = (self.0 % wad).as_u128();
+        write!(f, "{}.{:018}", self.0 / wad, fraction)
+    }
+}
+
+/// Parses a non-negative decimal such as `12.5`, with at most `SCALE` decimal places
+impl FromStr for Decimal {
+    type Err = ProgramError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (integer, fraction) = match s.split_once('.') {
+            Some((integer, fraction)) => (integer, Some(fraction)),
+            None => (s, None),
+        };
+        let is_digits = |part: &str| !part.is_empty() && part.bytes().all(|byte| byte.is_ascii_digit());
+        let fraction_is_valid = match fraction {
+            Some(fraction) => is_digits(fraction) && fraction.len() <= SCALE as usize,
+            None => true,
+        };
+        if !is_digits(integer) || !fraction_is_valid {
+            msg!("Invalid decimal {}", s);
+            return Err(ProgramError::InvalidArgument);
+        }
+
+        let integer = integer.parse::<u64>().map_err(|_| ProgramError::InvalidArgument)?;
+        let mut scaled_fraction = U192::zero();
+        if let Some(fraction) = fraction {
+            let digits = fraction.parse::<u64>().map_err(|_| ProgramError::InvalidArgument)?;
+            scaled_fraction = U192::from(digits) * pow10(SCALE - fraction.len() as u32)?;
+        }
+        Decimal::from(integer).try_add(Decimal(scaled_fraction))
+    }
+}
+
+pub(crate) fn pow10(exponent: u32) -> Result<U192, ProgramError> {
+    U192::from(10u8).checked_pow(U192::from(exponent)).ok_or_else(|| {
+        msg!("Math overflow: 10^{}", exponent);
+        OracleError::MathOverflow.into()
+    })
+}
+
+fn to_u64(value: U192, decimal: &Decimal) -> Result<u64, ProgramError> {
+    if value > U192::from(u64::MAX) {
+        msg!("Math overflow: {} does not fit in u64", decimal);
+        return Err(OracleError::MathOverflow.into());
+    }
+    Ok(value.as_u64())
+}
+
+fn overflow(op: &str, lhs: Decimal, rhs: Decimal) -> ProgramError {
+    msg!("Math overflow: {} {} {}", lhs, op, rhs);
+    OracleError::MathOverflow.into()
+}
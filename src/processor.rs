@@ -2,24 +2,24 @@ use solana_program::{
     account_info::{next_account_info, AccountInfo},
     entrypoint::ProgramResult,
     msg,
-    program::{invoke, invoke_signed},
-    instruction::{AccountMeta, Instruction},
+    program::{invoke, set_return_data},
     program_error::ProgramError,
     program_pack::{IsInitialized, Pack},
     pubkey::Pubkey,
-    sysvar::{rent::Rent, Sysvar},
+    sysvar::{clock::Clock, rent::Rent, Sysvar},
 };
 
 use spl_token::state::Account as TokenAccount;
 use spl_token::state::Mint as Mint;
 
 
-use pyth_client::{AccountType, CorpAction, Mapping, Price, PriceStatus, PriceType, Product, cast};
-use bytemuck::{cast_slice_mut, from_bytes_mut, try_cast_slice_mut};
-use std::cell::RefMut;
+use pyth_client::{CorpAction, PriceStatus, PriceType};
 solana_program::declare_id!("BpfProgram1111111111111111111111111111111111");
 
-use crate::{error::OracleError, instruction::OracleInstruction, states::Oracle, states::AMM};
+use std::convert::TryFrom;
+use std::mem::size_of;
+
+use crate::{cpi, curve::{curve_from_config, Curve, SwapDecimals}, error::OracleError, math::{Decimal, Rounding}, instruction::OracleInstruction, states::CurveConfig, states::Oracle, states::OraclePrice, states::TradeQuote, states::AMM};
 
 pub struct Processor;
 impl Processor {
@@ -31,80 +31,99 @@ impl Processor {
     let instruction = OracleInstruction::unpack(instruction_data)?;
 
         match instruction {
-            OracleInstruction::InitOracle { amm_program_id, lpcp_program_id, ucp_program_id } => {
+            OracleInstruction::InitOracle {
+                amm_program_id,
+                lpcp_program_id,
+                ucp_program_id,
+                pyth_program_id,
+                oracle_band_bps,
+                trade_fee_bps,
+                keeper_reward,
+                keeper_reward_interval_slots,
+                min_correction_interval_slots,
+                correction_override_bps,
+                max_correction_amount,
+                max_correction_bps,
+            } => {
                 msg!("Instruction: InitOracle");
-                Self::process_init_oracle(accounts, amm_program_id, lpcp_program_id, ucp_program_id, program_id)
+                Self::process_init_oracle(
+                    accounts,
+                    amm_program_id,
+                    lpcp_program_id,
+                    ucp_program_id,
+                    pyth_program_id,
+                    oracle_band_bps,
+                    trade_fee_bps,
+                    keeper_reward,
+                    keeper_reward_interval_slots,
+                    min_correction_interval_slots,
+                    correction_override_bps,
+                    max_correction_amount,
+                    max_correction_bps,
+                    program_id,
+                )
             }
-            OracleInstruction::TradeUSDI { amount } => {
+            OracleInstruction::TradeUSDI { amount, min_amount_out, expiry_slot } => {
                 msg!("Instruction: TradeUSDI");
-                Self::process_trade_usdi(accounts, amount, program_id)
+                Self::process_trade_usdi(accounts, amount, min_amount_out, expiry_slot, true, program_id)
             }
-            OracleInstruction::TradeiAsset { amount } => {
+            OracleInstruction::TradeiAsset { amount, min_amount_out, expiry_slot } => {
                 msg!("Instruction: TradeiAsset");
-                Self::process_trade_iasset(accounts, amount, program_id)
+                Self::process_trade_iasset(accounts, amount, min_amount_out, expiry_slot, program_id)
             }
             OracleInstruction::CollateralCorrection { num_amms } => {
                 msg!("Instruction: CollateralCorrection");
-
-                // Collect oracle price
-                
-                let account_info_iter = &mut accounts.iter().peekable();
-                let pyth_product_info = next_account_info(account_info_iter)?;
-                let pyth_price_info = next_account_info(account_info_iter)?;
-
-                let pyth_product_data = &pyth_product_info.try_borrow_data()?;
-                let pyth_product = pyth_client::cast::<pyth_client::Product>(pyth_product_data);
-
-                //Checks for pyth magic number
-                if pyth_product.magic != pyth_client::MAGIC {
-                    msg!("Pyth product account provided is not valid Pyth acccount");
-                    return Err(ProgramError::InvalidArgument.into());
-                }
-                if pyth_product.atype != pyth_client::AccountType::Product as u32 {
-                    msg!("Pyth product account provided is not a valid Pyth product account");
-                    return Err(ProgramError::InvalidArgument.into());
-                }
-
-                if pyth_product.ver != pyth_client::VERSION_2 {
-                    msg!("Pyth product account provided has a different version than the Pyth client");
-                    return Err(ProgramError::InvalidArgument.into());
-                }
-
-                if !pyth_product.px_acc.is_valid() {
-                    msg!("Pyth product price account is invalid");
-                    return Err(ProgramError::InvalidArgument.into());
-                }
-
-                let pyth_price_pubkey = Pubkey::new(&pyth_product.px_acc.val);
-                if &pyth_price_pubkey != pyth_price_info.key {
-                    msg!("Pyth product price account does not match the Pyth price provided");
-                    return Err(ProgramError::InvalidArgument.into());
-                }
-
-                let pyth_price_data = &pyth_price_info.try_borrow_data()?;
-                let pyth_price = pyth_client::cast::<pyth_client::Price>(pyth_price_data);
-                //oracle_price is in f64
-                let exponent = pyth_price.expo;
-                let base_oracle_price = pyth_price.agg.price as f64;
-                let oracle_price = base_oracle_price.powi(exponent);
-
-                msg!(" price account .. {:?}", pyth_price_info.key);
-                msg!(" price type ... {}", get_price_type(&pyth_price.ptype));
-                msg!(" status .... {}", get_status(&pyth_price.agg.status));
-                msg!(" price ....{}", oracle_price);
-
-                Self::process_collateral_correction(accounts, oracle_price, num_amms, program_id)
+                Self::process_collateral_correction(accounts, num_amms, program_id)
+            }
+            OracleInstruction::TradeRoute { amount, min_amount_out, expiry_slot } => {
+                msg!("Instruction: TradeRoute");
+                Self::process_trade_route(accounts, amount, min_amount_out, expiry_slot, program_id)
+            }
+            OracleInstruction::Quote { amount, usdi_in } => {
+                msg!("Instruction: Quote");
+                Self::process_quote(accounts, amount, usdi_in, program_id)
+            }
+            OracleInstruction::GetPrice => {
+                msg!("Instruction: GetPrice");
+                Self::process_get_price(accounts, program_id)
+            }
+            OracleInstruction::SetPause { paused_flags } => {
+                msg!("Instruction: SetPause");
+                Self::process_set_pause(accounts, paused_flags, program_id)
+            }
+            OracleInstruction::WithdrawFees { amount } => {
+                msg!("Instruction: WithdrawFees");
+                Self::process_withdraw_fees(accounts, amount, program_id)
+            }
+            OracleInstruction::SetCurve { curve_type, amplification } => {
+                msg!("Instruction: SetCurve");
+                Self::process_set_curve(accounts, curve_type, amplification, program_id)
             }
         }
     }
 
+    #[allow(clippy::too_many_arguments)]
     fn process_init_oracle(
         accounts: &[AccountInfo],
         amm_program_id: Pubkey,
         lpcp_program_id: Pubkey,
         ucp_program_id: Pubkey,
+        pyth_program_id: Pubkey,
+        oracle_band_bps: u64,
+        trade_fee_bps: u64,
+        keeper_reward: u64,
+        keeper_reward_interval_slots: u64,
+        min_correction_interval_slots: u64,
+        correction_override_bps: u64,
+        max_correction_amount: u64,
+        max_correction_bps: u64,
         program_id: &Pubkey,
     ) -> ProgramResult {
+        if trade_fee_bps > 10_000 {
+            msg!("Trade fee of {} bps is more than 100%", trade_fee_bps);
+            return Err(ProgramError::InvalidArgument);
+        }
+
         let account_info_iter = &mut accounts.iter();
 
 
@@ -137,11 +156,40 @@ impl Processor {
             return Err(ProgramError::AccountAlreadyInitialized);
         }
 
+        //Each Oracle gets its own authority PDA, seeded by the Oracle account key
+
+        let (pda, bump_seed) = cpi::find_oracle_pda(oracle_account.key, program_id);
+
         oracle_info.is_initialized = true;
         oracle_info.fee_token_account_pubkey = *temp_fee_token_account.key;
         oracle_info.amm_program_id = amm_program_id;
         oracle_info.lpcp_program_id = lpcp_program_id;
         oracle_info.ucp_program_id = ucp_program_id;
+        oracle_info.oracle_band_bps = oracle_band_bps;
+        oracle_info.trade_fee_bps = trade_fee_bps;
+        oracle_info.keeper_reward = keeper_reward;
+        oracle_info.keeper_reward_interval_slots = keeper_reward_interval_slots;
+        oracle_info.last_keeper_reward_slot = 0;
+        oracle_info.min_correction_interval_slots = min_correction_interval_slots;
+        oracle_info.correction_override_bps = correction_override_bps;
+        oracle_info.max_correction_amount = max_correction_amount;
+        oracle_info.max_correction_bps = max_correction_bps;
+        oracle_info.admin_pubkey = *initializer_account.key;
+        oracle_info.paused_flags = 0;
+        oracle_info.bump_seed = bump_seed;
+
+        let token_program = next_account_info(account_info_iter)?;
+
+
+
+        //Bind the Oracle to one Pyth price feed, checked on every later price load
+
+        let pyth_product_info = next_account_info(account_info_iter)?;
+        let pyth_price_info = next_account_info(account_info_iter)?;
+        oracle_info.pyth_program_id = pyth_program_id;
+        oracle_info.pyth_product_pubkey = *pyth_product_info.key;
+        oracle_info.pyth_price_pubkey = *pyth_price_info.key;
+        load_pyth_price(&oracle_info, pyth_product_info, pyth_price_info)?;
 
         Oracle::pack(oracle_info, &mut oracle_account.data.borrow_mut())?;
 
@@ -149,16 +197,13 @@ impl Processor {
         
         //Create and call instructions to initialize Oracle
 
-        let (pda, _bump_seed) = Pubkey::find_program_address(&[b"incept"], program_id);
-
-        let token_program = next_account_info(account_info_iter)?;
         let owner_change_ix = spl_token::instruction::set_authority(
             token_program.key,
             temp_fee_token_account.key,
             Some(&pda),
             spl_token::instruction::AuthorityType::AccountOwner,
             initializer_account.key,
-            &[&initializer_account.key],
+            &[initializer_account.key],
         )?;
 
         msg!("Calling the token program to transfer token account ownership...");
@@ -177,8 +222,13 @@ impl Processor {
     fn process_trade_usdi(
         accounts: &[AccountInfo],
         iasset_amount: u64,
+        min_amount_out: u64,
+        expiry_slot: Option<u64>,
+        charge_trade_fee: bool,
         program_id: &Pubkey,
     ) -> ProgramResult {
+        check_expiry(expiry_slot)?;
+
         let account_info_iter = &mut accounts.iter();
 
 
@@ -187,12 +237,19 @@ impl Processor {
         let pda_fee_token_account = next_account_info(account_info_iter)?;
 
         let oracle_account = next_account_info(account_info_iter)?;
+        if oracle_account.owner != program_id {
+            return Err(ProgramError::IncorrectProgramId);
+        }
         let oracle_info = Oracle::unpack_unchecked(&oracle_account.data.borrow())?;
+        check_not_paused(&oracle_info, Oracle::PAUSE_TRADING)?;
         if oracle_info.fee_token_account_pubkey != *pda_fee_token_account.key{
             return Err(ProgramError::InvalidAccountData);
         }
 
-        let (pda, bump_seed) = Pubkey::find_program_address(&[b"incept"], program_id);
+        let signer = cpi::OracleSigner {
+            oracle_key: oracle_account.key,
+            bump_seed: oracle_info.bump_seed,
+        };
 
         let user_account = next_account_info(account_info_iter)?;
         let user_usdi_token_account = next_account_info(account_info_iter)?;
@@ -204,104 +261,85 @@ impl Processor {
         let token_program = next_account_info(account_info_iter)?;
         let amm_pda_account = next_account_info(account_info_iter)?;
 
+        let ucp_account = next_account_info(account_info_iter)?;
+        let ucp_collateral_token_account = next_account_info(account_info_iter)?;
+        let lpcp_collateral_token_account = next_account_info(account_info_iter)?;
+        let ucp_pda_account = next_account_info(account_info_iter)?;
 
+        let pda_account = next_account_info(account_info_iter)?;
+        check_oracle_pda(&signer, pda_account, program_id)?;
 
 
-        //Set up program id, accounts, and instruction data to call TradeUSDI AMM instruction
 
-        let amm_program_id = oracle_info.amm_program_id;
+        //Refuse to trade if the AMM price is outside the oracle band, when the band accounts are provided
 
-        let mut accounts = Vec::with_capacity(9);
-        accounts.push(AccountMeta::new(*pda_fee_token_account.key, true));
-        accounts.push(AccountMeta::new(*user_account.key, true));
-        accounts.push(AccountMeta::new(*user_usdi_token_account.key, false)); 
-        accounts.push(AccountMeta::new(*user_iasset_token_account.key, false));  
-        accounts.push(AccountMeta::new(*amm_pda_usdi_token_account.key, false));  
-        accounts.push(AccountMeta::new(*amm_pda_iasset_token_account.key, false));  
-        accounts.push(AccountMeta::new(*amm_account.key, false));   
-        accounts.push(AccountMeta::new_readonly(*token_program.key, false)); 
-        accounts.push(AccountMeta::new_readonly(*amm_pda_account.key, false));
+        check_oracle_band(&oracle_info, account_info_iter.as_slice(), amm_pda_usdi_token_account, amm_pda_iasset_token_account)?;
 
-        let mut data: Vec<u8> = Vec::with_capacity(5);
-        data.push(3);
-        data.extend_from_slice(&iasset_amount.to_le_bytes());
 
 
-        //Create and call instruction to trade USDI to the AMM
 
-        let trade_usdi_to_amm = Instruction {
-            program_id: amm_program_id,
-            accounts,
-            data,
-        };
-        
-        invoke_signed(
-            &trade_usdi_to_amm,
-            &[
-                pda_fee_token_account.clone(),
-                user_account.clone(),
-                user_usdi_token_account.clone(),
-                user_iasset_token_account.clone(),
-                amm_pda_usdi_token_account.clone(),
-                amm_pda_iasset_token_account.clone(),
-                amm_account.clone(),
-                token_program.clone(),
-                amm_pda_account.clone(),
-            ],
-            &[&[&b"incept"[..], &[bump_seed]]],
+        //Call TradeUSDI on the AMM
+
+        let usdi_balance_before = TokenAccount::unpack(&user_usdi_token_account.data.borrow())?.amount;
+        let iasset_balance_before = TokenAccount::unpack(&user_iasset_token_account.data.borrow())?.amount;
+
+        cpi::amm::trade_usdi(
+            &oracle_info.amm_program_id,
+            cpi::amm::TradeAccounts {
+                authority: pda_account,
+                user_account,
+                user_usdi_token_account,
+                user_iasset_token_account,
+                amm_pda_usdi_token_account,
+                amm_pda_iasset_token_account,
+                amm_account,
+                token_program,
+                amm_pda_account,
+            },
+            iasset_amount,
+            signer,
         )?;
 
 
 
-        //Find spread picked up by last trade
+        //Skim the protocol fee on the USDI paid into the fee token account, unless a route already took it
 
-        let amm_info = AMM::unpack_unchecked(&amm_account.data.borrow())?;
-        let ucp_to_lpcp_spread_amount = amm_info.last_trade_spread;
+        if charge_trade_fee {
+            let usdi_balance_after = TokenAccount::unpack(&user_usdi_token_account.data.borrow())?.amount;
+            let trade_fee = calc_trade_fee(usdi_balance_before.saturating_sub(usdi_balance_after), oracle_info.trade_fee_bps)?;
+            collect_trade_fee(trade_fee, user_usdi_token_account, pda_fee_token_account, user_account, token_program)?;
+        }
 
-        let ucp_account = next_account_info(account_info_iter)?;
-        let ucp_collateral_token_account = next_account_info(account_info_iter)?;
-        let lpcp_collateral_token_account = next_account_info(account_info_iter)?;
-        let ucp_pda_account = next_account_info(account_info_iter)?;
 
 
+        //Ensure the user received at least the minimum amount of iAsset
 
-        //Set up program id, accounts, and instruction data to call SendCollateralLPCP UCP instruction
+        let iasset_balance_after = TokenAccount::unpack(&user_iasset_token_account.data.borrow())?.amount;
+        check_slippage(iasset_balance_after.saturating_sub(iasset_balance_before), min_amount_out)?;
 
-        let ucp_program_id = oracle_info.ucp_program_id;
 
-        let mut accounts = Vec::with_capacity(6);
-        accounts.push(AccountMeta::new(*pda_fee_token_account.key, true));
-        accounts.push(AccountMeta::new(*ucp_collateral_token_account.key, true));
-        accounts.push(AccountMeta::new(*lpcp_collateral_token_account.key, false)); 
-        accounts.push(AccountMeta::new(*ucp_account.key, false));  
-        accounts.push(AccountMeta::new_readonly(*token_program.key, false)); 
-        accounts.push(AccountMeta::new_readonly(*ucp_pda_account.key, false));
 
-        let mut data: Vec<u8> = Vec::with_capacity(5);
-        data.push(3);
-        data.extend_from_slice(&ucp_to_lpcp_spread_amount.to_le_bytes());
+        //Find spread picked up by last trade
 
+        let amm_info = AMM::unpack_unchecked(&amm_account.data.borrow())?;
+        let ucp_to_lpcp_spread_amount = amm_info.last_trade_spread;
 
 
-        //Create and call instruction to send collateral from the UCP to the LPCP
 
-        let send_collateral_to_lpcp = Instruction {
-            program_id: ucp_program_id,
-            accounts,
-            data,
-        };
-        
-        invoke_signed(
-            &send_collateral_to_lpcp,
-            &[
-                pda_fee_token_account.clone(),
-                ucp_collateral_token_account.clone(),
-                lpcp_collateral_token_account.clone(),
-                ucp_account.clone(),
-                token_program.clone(),
-                ucp_pda_account.clone(),
-            ],
-            &[&[&b"incept"[..], &[bump_seed]]],
+        //Send the spread from the UCP to the LPCP
+
+        cpi::ucp::send_collateral(
+            &oracle_info.ucp_program_id,
+            cpi::ucp::SendCollateralAccounts {
+                authority: pda_account,
+                ucp_collateral_token_account,
+                lpcp_collateral_token_account,
+                ucp_account,
+                token_program,
+                ucp_pda_account,
+            },
+            ucp_to_lpcp_spread_amount,
+            signer,
         )?;
 
         Ok(())
@@ -310,8 +348,12 @@ impl Processor {
     fn process_trade_iasset(
         accounts: &[AccountInfo],
         iasset_amount: u64,
+        min_amount_out: u64,
+        expiry_slot: Option<u64>,
         program_id: &Pubkey,
     ) -> ProgramResult {
+        check_expiry(expiry_slot)?;
+
         let account_info_iter = &mut accounts.iter();
 
 
@@ -320,12 +362,19 @@ impl Processor {
         let pda_fee_token_account = next_account_info(account_info_iter)?;
 
         let oracle_account = next_account_info(account_info_iter)?;
+        if oracle_account.owner != program_id {
+            return Err(ProgramError::IncorrectProgramId);
+        }
         let oracle_info = Oracle::unpack_unchecked(&oracle_account.data.borrow())?;
+        check_not_paused(&oracle_info, Oracle::PAUSE_TRADING)?;
         if oracle_info.fee_token_account_pubkey != *pda_fee_token_account.key{
             return Err(ProgramError::InvalidAccountData);
         }
 
-        let (pda, bump_seed) = Pubkey::find_program_address(&[b"incept"], program_id);
+        let signer = cpi::OracleSigner {
+            oracle_key: oracle_account.key,
+            bump_seed: oracle_info.bump_seed,
+        };
 
         let user_account = next_account_info(account_info_iter)?;
         let user_usdi_token_account = next_account_info(account_info_iter)?;
@@ -337,133 +386,516 @@ impl Processor {
         let token_program = next_account_info(account_info_iter)?;
         let amm_pda_account = next_account_info(account_info_iter)?;
 
+        let ucp_account = next_account_info(account_info_iter)?;
+        let ucp_collateral_token_account = next_account_info(account_info_iter)?;
+        let lpcp_collateral_token_account = next_account_info(account_info_iter)?;
+        let ucp_pda_account = next_account_info(account_info_iter)?;
 
+        let pda_account = next_account_info(account_info_iter)?;
+        check_oracle_pda(&signer, pda_account, program_id)?;
 
 
-        //Set up program id, accounts, and instruction data to call TradeiAsset AMM instruction
 
-        let amm_program_id = oracle_info.amm_program_id;
+        //Refuse to trade if the AMM price is outside the oracle band, when the band accounts are provided
 
-        let mut accounts = Vec::with_capacity(9);
-        accounts.push(AccountMeta::new(*pda_fee_token_account.key, true));
-        accounts.push(AccountMeta::new(*user_account.key, true));
-        accounts.push(AccountMeta::new(*user_usdi_token_account.key, false)); 
-        accounts.push(AccountMeta::new(*user_iasset_token_account.key, false));  
-        accounts.push(AccountMeta::new(*amm_pda_usdi_token_account.key, false));  
-        accounts.push(AccountMeta::new(*amm_pda_iasset_token_account.key, false));  
-        accounts.push(AccountMeta::new(*amm_account.key, false));   
-        accounts.push(AccountMeta::new_readonly(*token_program.key, false)); 
-        accounts.push(AccountMeta::new_readonly(*amm_pda_account.key, false));
+        check_oracle_band(&oracle_info, account_info_iter.as_slice(), amm_pda_usdi_token_account, amm_pda_iasset_token_account)?;
 
-        let mut data: Vec<u8> = Vec::with_capacity(5);
-        data.push(4);
-        data.extend_from_slice(&iasset_amount.to_le_bytes());
 
 
-        //Create and call instruction to trade iAsset to the AMM
 
-        let trade_usdi_to_amm = Instruction {
-            program_id: amm_program_id,
-            accounts,
-            data,
-        };
-        
-        invoke_signed(
-            &trade_usdi_to_amm,
-            &[
-                pda_fee_token_account.clone(),
-                user_account.clone(),
-                user_usdi_token_account.clone(),
-                user_iasset_token_account.clone(),
-                amm_pda_usdi_token_account.clone(),
-                amm_pda_iasset_token_account.clone(),
-                amm_account.clone(),
-                token_program.clone(),
-                amm_pda_account.clone(),
-            ],
-            &[&[&b"incept"[..], &[bump_seed]]],
+        //Call TradeiAsset on the AMM
+
+        let usdi_balance_before = TokenAccount::unpack(&user_usdi_token_account.data.borrow())?.amount;
+
+        cpi::amm::trade_iasset(
+            &oracle_info.amm_program_id,
+            cpi::amm::TradeAccounts {
+                authority: pda_account,
+                user_account,
+                user_usdi_token_account,
+                user_iasset_token_account,
+                amm_pda_usdi_token_account,
+                amm_pda_iasset_token_account,
+                amm_account,
+                token_program,
+                amm_pda_account,
+            },
+            iasset_amount,
+            signer,
         )?;
 
 
 
+        //Skim the protocol fee on the USDI received into the fee token account
+
+        let usdi_balance_after = TokenAccount::unpack(&user_usdi_token_account.data.borrow())?.amount;
+        let usdi_received = usdi_balance_after.saturating_sub(usdi_balance_before);
+        let trade_fee = calc_trade_fee(usdi_received, oracle_info.trade_fee_bps)?;
+        collect_trade_fee(trade_fee, user_usdi_token_account, pda_fee_token_account, user_account, token_program)?;
+
+
+
+        //Ensure the user received at least the minimum amount of USDI, net of the fee
+
+        check_slippage(usdi_received.saturating_sub(trade_fee), min_amount_out)?;
+
+
+
         //Find spread picked up by last trade
 
         let amm_info = AMM::unpack_unchecked(&amm_account.data.borrow())?;
         let ucp_to_lpcp_spread_amount = amm_info.last_trade_spread;
 
+
+
+        //Send the spread from the UCP to the LPCP
+
+        cpi::ucp::send_collateral(
+            &oracle_info.ucp_program_id,
+            cpi::ucp::SendCollateralAccounts {
+                authority: pda_account,
+                ucp_collateral_token_account,
+                lpcp_collateral_token_account,
+                ucp_account,
+                token_program,
+                ucp_pda_account,
+            },
+            ucp_to_lpcp_spread_amount,
+            signer,
+        )?;
+
+        Ok(())
+    }
+
+    fn process_trade_route(
+        accounts: &[AccountInfo],
+        iasset_amount: u64,
+        min_amount_out: u64,
+        expiry_slot: Option<u64>,
+        program_id: &Pubkey,
+    ) -> ProgramResult {
+        check_expiry(expiry_slot)?;
+
+        let account_info_iter = &mut accounts.iter();
+
+
+        //Collect accounts shared by both legs of the route
+
+        let pda_fee_token_account = next_account_info(account_info_iter)?;
+        let oracle_account = next_account_info(account_info_iter)?;
+        let user_account = next_account_info(account_info_iter)?;
+        let user_usdi_token_account = next_account_info(account_info_iter)?;
+        let user_sell_iasset_token_account = next_account_info(account_info_iter)?;
+        let user_buy_iasset_token_account = next_account_info(account_info_iter)?;
+        let token_program = next_account_info(account_info_iter)?;
+
         let ucp_account = next_account_info(account_info_iter)?;
         let ucp_collateral_token_account = next_account_info(account_info_iter)?;
         let lpcp_collateral_token_account = next_account_info(account_info_iter)?;
         let ucp_pda_account = next_account_info(account_info_iter)?;
+        let pda_account = next_account_info(account_info_iter)?;
+
+        let sell_amm_pda_usdi_token_account = next_account_info(account_info_iter)?;
+        let sell_amm_pda_iasset_token_account = next_account_info(account_info_iter)?;
+        let sell_amm_account = next_account_info(account_info_iter)?;
+        let sell_amm_pda_account = next_account_info(account_info_iter)?;
+
+        let buy_amm_pda_usdi_token_account = next_account_info(account_info_iter)?;
+        let buy_amm_pda_iasset_token_account = next_account_info(account_info_iter)?;
+        let buy_amm_account = next_account_info(account_info_iter)?;
+        let buy_amm_pda_account = next_account_info(account_info_iter)?;
+        if sell_amm_account.key == buy_amm_account.key {
+            msg!("Route sells into and buys from the same AMM {}", sell_amm_account.key);
+            return Err(OracleError::DuplicateAmm.into());
+        }
 
 
 
-        //Set up program id, accounts, and instruction data to call SendCollateralLPCP UCP instruction
-
-        let ucp_program_id = oracle_info.ucp_program_id;
+        //Pass the oracle band accounts to both legs with each leg's iAsset mint, leaving them out when none are provided
 
-        let mut accounts = Vec::with_capacity(6);
-        accounts.push(AccountMeta::new(*pda_fee_token_account.key, true));
-        accounts.push(AccountMeta::new(*ucp_collateral_token_account.key, true));
-        accounts.push(AccountMeta::new(*lpcp_collateral_token_account.key, false)); 
-        accounts.push(AccountMeta::new(*ucp_account.key, false));  
-        accounts.push(AccountMeta::new_readonly(*token_program.key, false)); 
-        accounts.push(AccountMeta::new_readonly(*ucp_pda_account.key, false));
+        let (sell_band_accounts, buy_band_accounts) = match account_info_iter.as_slice() {
+            [pyth_product_info, pyth_price_info, usdi_mint_info, sell_iasset_mint_info, buy_iasset_mint_info, ..] => (
+                vec![pyth_product_info.clone(), pyth_price_info.clone(), usdi_mint_info.clone(), sell_iasset_mint_info.clone()],
+                vec![pyth_product_info.clone(), pyth_price_info.clone(), usdi_mint_info.clone(), buy_iasset_mint_info.clone()],
+            ),
+            _ => (Vec::new(), Vec::new()),
+        };
 
-        let mut data: Vec<u8> = Vec::with_capacity(5);
-        data.push(3);
-        data.extend_from_slice(&ucp_to_lpcp_spread_amount.to_le_bytes());
 
 
+        //Sell iAsset for USDI through the first AMM, settling its spread to the LPCP and taking the route's only trade fee
 
-        //Create and call instruction to send collateral from the UCP to the LPCP
+        let usdi_balance_before = TokenAccount::unpack(&user_usdi_token_account.data.borrow())?.amount;
 
-        let send_collateral = Instruction {
-            program_id: ucp_program_id,
-            accounts,
-            data,
-        };
-        
-        invoke_signed(
-            &send_collateral,
+        Self::process_trade_iasset(
             &[
-                pda_fee_token_account.clone(),
-                ucp_collateral_token_account.clone(),
-                lpcp_collateral_token_account.clone(),
-                ucp_account.clone(),
-                token_program.clone(),
-                ucp_pda_account.clone(),
-            ],
-            &[&[&b"incept"[..], &[bump_seed]]],
+                &[
+                    pda_fee_token_account.clone(),
+                    oracle_account.clone(),
+                    user_account.clone(),
+                    user_usdi_token_account.clone(),
+                    user_sell_iasset_token_account.clone(),
+                    sell_amm_pda_usdi_token_account.clone(),
+                    sell_amm_pda_iasset_token_account.clone(),
+                    sell_amm_account.clone(),
+                    token_program.clone(),
+                    sell_amm_pda_account.clone(),
+                    ucp_account.clone(),
+                    ucp_collateral_token_account.clone(),
+                    lpcp_collateral_token_account.clone(),
+                    ucp_pda_account.clone(),
+                    pda_account.clone(),
+                ][..],
+                &sell_band_accounts,
+            ]
+            .concat(),
+            iasset_amount,
+            0,
+            None,
+            program_id,
         )?;
 
+        let usdi_balance_after = TokenAccount::unpack(&user_usdi_token_account.data.borrow())?.amount;
+        let usdi_amount = usdi_balance_after.saturating_sub(usdi_balance_before);
+
+
+
+        //Buy iAsset with the USDI received net of the fee through the second AMM, enforcing the end-to-end minimum
+
+        Self::process_trade_usdi(
+            &[
+                &[
+                    pda_fee_token_account.clone(),
+                    oracle_account.clone(),
+                    user_account.clone(),
+                    user_usdi_token_account.clone(),
+                    user_buy_iasset_token_account.clone(),
+                    buy_amm_pda_usdi_token_account.clone(),
+                    buy_amm_pda_iasset_token_account.clone(),
+                    buy_amm_account.clone(),
+                    token_program.clone(),
+                    buy_amm_pda_account.clone(),
+                    ucp_account.clone(),
+                    ucp_collateral_token_account.clone(),
+                    lpcp_collateral_token_account.clone(),
+                    ucp_pda_account.clone(),
+                    pda_account.clone(),
+                ][..],
+                &buy_band_accounts,
+            ]
+            .concat(),
+            usdi_amount,
+            min_amount_out,
+            None,
+            false,
+            program_id,
+        )
+    }
+
+    fn process_quote(
+        accounts: &[AccountInfo],
+        amount: u64,
+        usdi_in: bool,
+        program_id: &Pubkey,
+    ) -> ProgramResult {
+        let account_info_iter = &mut accounts.iter();
+
+
+        //Collect oracle price and AMM balances
+
+        let oracle_account = next_account_info(account_info_iter)?;
+        if oracle_account.owner != program_id {
+            return Err(ProgramError::IncorrectProgramId);
+        }
+        let oracle_info = Oracle::unpack(&oracle_account.data.borrow())?;
+        check_not_paused(&oracle_info, Oracle::PAUSE_QUOTE)?;
+        let pyth_product_info = next_account_info(account_info_iter)?;
+        let pyth_price_info = next_account_info(account_info_iter)?;
+        let oracle_price = load_oracle_price(&oracle_info, pyth_product_info, pyth_price_info)?;
+
+        let amm_account = next_account_info(account_info_iter)?;
+        let amm_info = AMM::unpack(&amm_account.data.borrow())?;
+        let amm_usdi_token_account = next_account_info(account_info_iter)?;
+        let amm_iasset_token_account = next_account_info(account_info_iter)?;
+        if amm_info.usdi_token_account_pubkey != *amm_usdi_token_account.key || amm_info.iasset_token_account_pubkey != *amm_iasset_token_account.key{
+            return Err(OracleError::MismatchedPDAAccountsForAMMs.into());
+        }
+        let curve_config_account = next_account_info(account_info_iter)?;
+        let curve = load_curve_config(curve_config_account, oracle_account, amm_account, program_id)?.curve()?;
+        let usdi_mint_account = next_account_info(account_info_iter)?;
+        let iasset_mint_account = next_account_info(account_info_iter)?;
+        let (usdi_amm_amount, usdi_decimals) = load_token_amount_and_decimals(amm_usdi_token_account, usdi_mint_account)?;
+        let (iasset_amm_amount, iasset_decimals) = load_token_amount_and_decimals(amm_iasset_token_account, iasset_mint_account)?;
+
+
+
+        //Price the trade against the AMM's curve, against its current price and against the oracle
+
+        let (amount_out, amount_out_at_amm_price, fair_value) = if usdi_in {
+            (
+                curve.sell_price(iasset_amm_amount, usdi_amm_amount, amount, SwapDecimals::new(iasset_decimals, usdi_decimals))?,
+                Decimal::from(amount).try_mul_u64(iasset_amm_amount)?.try_div_u64(usdi_amm_amount)?,
+                Decimal::from_fixed(amount, usdi_decimals)?.try_div(oracle_price)?.try_to_base_units(iasset_decimals)?,
+            )
+        } else {
+            (
+                curve.sell_price(usdi_amm_amount, iasset_amm_amount, amount, SwapDecimals::new(usdi_decimals, iasset_decimals))?,
+                Decimal::from(amount).try_mul_u64(usdi_amm_amount)?.try_div_u64(iasset_amm_amount)?,
+                Decimal::from_fixed(amount, iasset_decimals)?.try_mul(oracle_price)?.try_to_base_units(usdi_decimals)?,
+            )
+        };
+
+        //The spread is what the trade loses to the AMM's curve against its current price
+        let amount_out_at_amm_price = to_u64(amount_out_at_amm_price, Rounding::RoundDown)?;
+        let spread = amount_out_at_amm_price.saturating_sub(amount_out);
+
+        //Charge the trade fee like the trades do, on top of the USDI paid in or out of the USDI received
+        let (amount_out, trade_fee) = if usdi_in {
+            (amount_out, calc_trade_fee(amount, oracle_info.trade_fee_bps)?)
+        } else {
+            let trade_fee = calc_trade_fee(amount_out, oracle_info.trade_fee_bps)?;
+            (amount_out.saturating_sub(trade_fee), trade_fee)
+        };
+        let quote = TradeQuote {
+            amount_out,
+            spread,
+            fair_value: to_u64(fair_value, Rounding::RoundDown)?,
+            trade_fee,
+        };
+
+        let mut data = [0u8; TradeQuote::LEN];
+        TradeQuote::pack(quote, &mut data)?;
+        set_return_data(&data);
+
+        Ok(())
+    }
+
+    fn process_get_price(
+        accounts: &[AccountInfo],
+        program_id: &Pubkey,
+    ) -> ProgramResult {
+        let account_info_iter = &mut accounts.iter();
+
+
+        //Validate the Oracle's Pyth accounts and return the normalized price
+
+        let oracle_account = next_account_info(account_info_iter)?;
+        if oracle_account.owner != program_id {
+            return Err(ProgramError::IncorrectProgramId);
+        }
+        let oracle_info = Oracle::unpack(&oracle_account.data.borrow())?;
+        check_not_paused(&oracle_info, Oracle::PAUSE_GET_PRICE)?;
+        let pyth_product_info = next_account_info(account_info_iter)?;
+        let pyth_price_info = next_account_info(account_info_iter)?;
+        let oracle_price = load_pyth_price(&oracle_info, pyth_product_info, pyth_price_info)?;
+
+        let mut data = [0u8; OraclePrice::LEN];
+        OraclePrice::pack(oracle_price, &mut data)?;
+        set_return_data(&data);
+
+        Ok(())
+    }
+
+    fn process_set_pause(
+        accounts: &[AccountInfo],
+        paused_flags: u8,
+        program_id: &Pubkey,
+    ) -> ProgramResult {
+        let account_info_iter = &mut accounts.iter();
+
+
+        //Ensure the admin signs off on instruction
+
+        let admin_account = next_account_info(account_info_iter)?;
+        if !admin_account.is_signer {
+            return Err(ProgramError::MissingRequiredSignature);
+        }
+
+        let oracle_account = next_account_info(account_info_iter)?;
+        if oracle_account.owner != program_id {
+            return Err(ProgramError::IncorrectProgramId);
+        }
+        let mut oracle_info = Oracle::unpack(&oracle_account.data.borrow())?;
+        if oracle_info.admin_pubkey != *admin_account.key {
+            return Err(OracleError::InvalidAdmin.into());
+        }
+
+
+
+        //Replace the paused flags
+
+        oracle_info.paused_flags = paused_flags;
+        Oracle::pack(oracle_info, &mut oracle_account.data.borrow_mut())?;
+
+        Ok(())
+    }
+
+    fn process_withdraw_fees(
+        accounts: &[AccountInfo],
+        amount: u64,
+        program_id: &Pubkey,
+    ) -> ProgramResult {
+        let account_info_iter = &mut accounts.iter();
+
+
+        //Ensure the admin signs off on instruction
+
+        let admin_account = next_account_info(account_info_iter)?;
+        if !admin_account.is_signer {
+            return Err(ProgramError::MissingRequiredSignature);
+        }
+
+        let oracle_account = next_account_info(account_info_iter)?;
+        if oracle_account.owner != program_id {
+            return Err(ProgramError::IncorrectProgramId);
+        }
+        let oracle_info = Oracle::unpack(&oracle_account.data.borrow())?;
+        check_not_paused(&oracle_info, Oracle::PAUSE_WITHDRAW_FEES)?;
+        if oracle_info.admin_pubkey != *admin_account.key {
+            return Err(OracleError::InvalidAdmin.into());
+        }
+
+
+
+        //Ensure correct account information
+
+        let pda_fee_token_account = next_account_info(account_info_iter)?;
+        if oracle_info.fee_token_account_pubkey != *pda_fee_token_account.key{
+            return Err(ProgramError::InvalidAccountData);
+        }
+
+        let destination_token_account = next_account_info(account_info_iter)?;
+        let token_program = next_account_info(account_info_iter)?;
+
+        let signer = cpi::OracleSigner {
+            oracle_key: oracle_account.key,
+            bump_seed: oracle_info.bump_seed,
+        };
+        let pda_account = next_account_info(account_info_iter)?;
+        check_oracle_pda(&signer, pda_account, program_id)?;
+
+
+
+        //Transfer fees out of the fee token account, signed by the Oracle PDA
+
+        msg!("Calling the token program to withdraw fees...");
+        transfer_from_fee_account(
+            amount,
+            pda_fee_token_account,
+            destination_token_account,
+            pda_account,
+            token_program,
+            signer,
+        )
+    }
+
+    fn process_set_curve(
+        accounts: &[AccountInfo],
+        curve_type: u8,
+        amplification: u64,
+        program_id: &Pubkey,
+    ) -> ProgramResult {
+        curve_from_config(curve_type, amplification)?;
+
+        let account_info_iter = &mut accounts.iter();
+
+
+        //Ensure the admin signs off on instruction
+
+        let admin_account = next_account_info(account_info_iter)?;
+        if !admin_account.is_signer {
+            return Err(ProgramError::MissingRequiredSignature);
+        }
+
+        let oracle_account = next_account_info(account_info_iter)?;
+        if oracle_account.owner != program_id {
+            return Err(ProgramError::IncorrectProgramId);
+        }
+        let oracle_info = Oracle::unpack(&oracle_account.data.borrow())?;
+        if oracle_info.admin_pubkey != *admin_account.key {
+            return Err(OracleError::InvalidAdmin.into());
+        }
+
+
+
+        //Ensures the curve config account is rent exempt and belongs to this Oracle and AMM
+
+        let curve_config_account = next_account_info(account_info_iter)?;
+        if curve_config_account.owner != program_id {
+            return Err(ProgramError::IncorrectProgramId);
+        }
+        let rent = &Rent::from_account_info(next_account_info(account_info_iter)?)?;
+        if !rent.is_exempt(curve_config_account.lamports(), curve_config_account.data_len()) {
+            return Err(OracleError::NotRentExempt.into());
+        }
+
+        let amm_account = next_account_info(account_info_iter)?;
+        AMM::unpack(&amm_account.data.borrow())?;
+
+        let mut curve_config = CurveConfig::unpack_unchecked(&curve_config_account.data.borrow())?;
+        if curve_config.is_initialized() && (curve_config.oracle_pubkey != *oracle_account.key || curve_config.amm_pubkey != *amm_account.key) {
+            msg!("Curve config belongs to another Oracle or AMM");
+            return Err(OracleError::InvalidCurve.into());
+        }
+
+
+
+        //Packs the curve selection into the curve config account
+
+        curve_config.is_initialized = true;
+        curve_config.oracle_pubkey = *oracle_account.key;
+        curve_config.amm_pubkey = *amm_account.key;
+        curve_config.curve_type = curve_type;
+        curve_config.amplification = amplification;
+        CurveConfig::pack(curve_config, &mut curve_config_account.data.borrow_mut())?;
+
         Ok(())
     }
 
     fn process_collateral_correction(
         accounts: &[AccountInfo],
-        oracle_price: f64,
         num_amms: u64,
         program_id: &Pubkey,
     ) -> ProgramResult {
+        if num_amms == 0 {
+            msg!("CollateralCorrection needs at least one AMM");
+            return Err(ProgramError::InvalidInstructionData);
+        }
         let account_info_iter = &mut accounts.iter();
 
 
         //Ensure correct account information
 
+        let pyth_product_info = next_account_info(account_info_iter)?;
+        let pyth_price_info = next_account_info(account_info_iter)?;
         let pda_fee_token_account = next_account_info(account_info_iter)?;
         
         let oracle_account = next_account_info(account_info_iter)?;
-        let oracle_info = Oracle::unpack_unchecked(&oracle_account.data.borrow())?;
+        if oracle_account.owner != program_id {
+            return Err(ProgramError::IncorrectProgramId);
+        }
+        let mut oracle_info = Oracle::unpack(&oracle_account.data.borrow())?;
+        check_not_paused(&oracle_info, Oracle::PAUSE_CORRECTION)?;
         if oracle_info.fee_token_account_pubkey != *pda_fee_token_account.key{
             return Err(ProgramError::InvalidAccountData);
         }
 
 
 
+        //Collect oracle price from the Oracle's own Pyth feed
+
+        let pyth_price = load_pyth_price(&oracle_info, pyth_product_info, pyth_price_info)?;
+        let oracle_price = oracle_price_to_decimal(&pyth_price)?;
+        let current_slot = Clock::get()?.slot;
+
+
+
         //Set necessary account information
 
-        let (pda, bump_seed) = Pubkey::find_program_address(&[b"incept"], program_id);
+        let signer = cpi::OracleSigner {
+            oracle_key: oracle_account.key,
+            bump_seed: oracle_info.bump_seed,
+        };
 
         let token_program = next_account_info(account_info_iter)?;
         let amm_pda_account = next_account_info(account_info_iter)?;
@@ -476,7 +908,19 @@ impl Processor {
         let lpcp_pda_account = next_account_info(account_info_iter)?;
         let ucp_pda_account = next_account_info(account_info_iter)?;
 
-        let mut transfer_amount = 0.0;
+        let keeper_account = next_account_info(account_info_iter)?;
+        if !keeper_account.is_signer {
+            return Err(ProgramError::MissingRequiredSignature);
+        }
+        let keeper_reward_token_account = next_account_info(account_info_iter)?;
+        let pda_account = next_account_info(account_info_iter)?;
+        check_oracle_pda(&signer, pda_account, program_id)?;
+        let usdi_mint_account = next_account_info(account_info_iter)?;
+        let usdi_decimals = u32::from(Mint::unpack(&usdi_mint_account.data.borrow())?.decimals);
+
+        let mut transfer_amount: i128 = 0;
+        let mut changed_supply = false;
+        let mut corrected_accounts: Vec<&Pubkey> = Vec::new();
 
 
         // Loop through amms
@@ -491,208 +935,361 @@ impl Processor {
             let amm_iasset_token_account_info = TokenAccount::unpack(&amm_iasset_token_account.data.borrow())?;
             let iasset_mint_account = next_account_info(account_info_iter)?;
             let iasset_mint_account_info = Mint::unpack(&iasset_mint_account.data.borrow())?;
-            if iasset_mint_account.key != &amm_iasset_token_account_info.mint{
+            if iasset_mint_account.key != &amm_iasset_token_account_info.mint || usdi_mint_account.key != &amm_usdi_token_account_info.mint{
                 return Err(OracleError::InvalidMintData.into());
             }
+            let iasset_decimals = u32::from(iasset_mint_account_info.decimals);
             if current_amm_info.usdi_token_account_pubkey != *amm_usdi_token_account.key || current_amm_info.iasset_token_account_pubkey != *amm_iasset_token_account.key{
                 return Err(OracleError::MismatchedPDAAccountsForAMMs.into());
             }
 
+            //Reject an AMM or iAsset token account passed twice, which would be corrected past its cap
 
+            if corrected_accounts.contains(&current_amm.key) || corrected_accounts.contains(&amm_iasset_token_account.key) {
+                msg!("AMM {} is passed more than once", current_amm.key);
+                return Err(OracleError::DuplicateAmm.into());
+            }
+            corrected_accounts.extend([current_amm.key, amm_iasset_token_account.key]);
+            let curve_config_account = next_account_info(account_info_iter)?;
+            let mut curve_config = load_curve_config(curve_config_account, oracle_account, current_amm, program_id)?;
+            let curve = curve_config.curve()?;
 
-            //Calculate number of iAsset to mint/burn
 
-            let user_owned_iasset = iasset_mint_account_info.supply - amm_iasset_token_account_info.amount;
-            let usdi_put_in = calc_sell_price_from_num_iasset(amm_usdi_token_account_info.amount, amm_iasset_token_account_info.amount, user_owned_iasset);
-            let current_lpr_usdi = (amm_usdi_token_account_info.amount - usdi_put_in) as f64;
-            let preferred_lpr = calc_lpr(oracle_price);
-            let num_iasset_to_mint = (current_lpr_usdi - preferred_lpr*preferred_lpr)/preferred_lpr;
 
-            if num_iasset_to_mint > 0.0{
+            //Rate limit each AMM's corrections, unless the price has moved past the override threshold
 
-                //Set up program id, accounts, and instruction data to call MintiAsset AMM instruction
+            check_correction_interval(&oracle_info, &curve_config, current_slot, pyth_price.price)?;
 
-                let amm_program_id = oracle_info.amm_program_id;
 
-                let mut accounts = Vec::with_capacity(6);
-                accounts.push(AccountMeta::new(*pda_fee_token_account.key, true));
-                accounts.push(AccountMeta::new(*amm_iasset_token_account.key, true));
-                accounts.push(AccountMeta::new(*iasset_mint_account.key, false)); 
-                accounts.push(AccountMeta::new(*current_amm.key, false));  
-                accounts.push(AccountMeta::new_readonly(*token_program.key, false)); 
-                accounts.push(AccountMeta::new_readonly(*amm_pda_account.key, false));
 
-                let mut data: Vec<u8> = Vec::with_capacity(5);
-                data.push(5);
-                let num_iasset_to_mint_u64 = to_u64(num_iasset_to_mint);
-                data.extend_from_slice(&num_iasset_to_mint_u64.to_le_bytes());
+            //Calculate number of iAsset to mint/burn
 
+            let balances = AmmBalances {
+                usdi_amount: amm_usdi_token_account_info.amount,
+                iasset_amount: amm_iasset_token_account_info.amount,
+                iasset_supply: iasset_mint_account_info.supply,
+                usdi_decimals,
+                iasset_decimals,
+            };
+            let correction = calc_correction(&oracle_info, curve.as_ref(), oracle_price, &balances)?;
+
+            let supply_accounts = cpi::amm::SupplyAccounts {
+                authority: pda_account,
+                amm_iasset_token_account,
+                iasset_mint_account,
+                amm_account: current_amm,
+                token_program,
+                amm_pda_account,
+            };
 
+            if correction.amount == 0 {
+                msg!("No iAsset to mint or burn for this AMM");
+            } else if correction.mint_iasset {
 
-                //Create and call instruction to mint iAsset to the AMM 
+                //Mint iAsset to the AMM
 
-                let mint_iasset_to_amm = Instruction {
-                    program_id: amm_program_id,
-                    accounts,
-                    data,
-                };
-        
-                invoke_signed(
-                    &mint_iasset_to_amm,
-                    &[
-                        pda_fee_token_account.clone(),
-                        amm_iasset_token_account.clone(),
-                        iasset_mint_account.clone(),
-                        current_amm.clone(),
-                        token_program.clone(),
-                        amm_pda_account.clone(),
-                    ],
-                    &[&[&b"incept"[..], &[bump_seed]]],
-                )?;
-                
-                
+                cpi::amm::mint_iasset(&oracle_info.amm_program_id, supply_accounts, correction.amount, signer)?;
             } else {
 
-                //Set up program id, accounts, and instruction to BurniAsset from the AMM 
+                //Burn iAsset from the AMM
+
+                cpi::amm::burn_iasset(&oracle_info.amm_program_id, supply_accounts, correction.amount, signer)?;
+            }
 
-                let amm_program_id = oracle_info.amm_program_id;
+            //Recalculate LPR from the AMM balances after the mint/burn and add to total amount that will need to be transfered between UCP and LPCP
+            //The LPR after rounds up against the LPR before rounding down, so collateral owed to the UCP rounds up and collateral paid out of it rounds down
 
-                let mut accounts = Vec::with_capacity(6);
-                accounts.push(AccountMeta::new(*pda_fee_token_account.key, true));
-                accounts.push(AccountMeta::new(*amm_iasset_token_account.key, true));
-                accounts.push(AccountMeta::new(*iasset_mint_account.key, false)); 
-                accounts.push(AccountMeta::new(*current_amm.key, false));  
-                accounts.push(AccountMeta::new_readonly(*token_program.key, false)); 
-                accounts.push(AccountMeta::new_readonly(*amm_pda_account.key, false));
+            let amm_usdi_amount_after_correction = TokenAccount::unpack(&amm_usdi_token_account.data.borrow())?.amount;
+            let amm_iasset_amount_after_correction = TokenAccount::unpack(&amm_iasset_token_account.data.borrow())?.amount;
+            if amm_iasset_amount_after_correction != amm_iasset_token_account_info.amount {
+                changed_supply = true;
+                let current_lpr_usdi_after_correction = calc_lpr_usdi(curve.as_ref(), amm_usdi_amount_after_correction, amm_iasset_amount_after_correction, correction.user_owned_iasset, usdi_decimals, iasset_decimals, Rounding::RoundUp)?;
+                transfer_amount = transfer_amount
+                    .checked_add(correction.lpr_usdi as i128 - current_lpr_usdi_after_correction as i128)
+                    .ok_or(OracleError::MathOverflow)?;
 
-                let mut data: Vec<u8> = Vec::with_capacity(5);
-                data.push(6);
-                let num_iasset_to_mint_u64 = to_u64(num_iasset_to_mint);
-                data.extend_from_slice(&num_iasset_to_mint_u64.to_le_bytes());
+                //Record this AMM's correction for rate limiting, only once it has been applied
 
+                curve_config.last_correction_slot = current_slot;
+                curve_config.last_correction_price = pyth_price.price;
+                CurveConfig::pack(curve_config, &mut curve_config_account.data.borrow_mut())?;
+            }
+        }
 
+        //Settle the net collateral moved by the corrections, skipping a zero amount
+
+        if transfer_amount == 0 {
+            msg!("No collateral to send between the UCP and the LPCP");
+        } else if transfer_amount > 0 {
+
+            //Send collateral from the UCP to the LPCP
+
+            cpi::ucp::send_collateral(
+                &oracle_info.ucp_program_id,
+                cpi::ucp::SendCollateralAccounts {
+                    authority: pda_account,
+                    ucp_collateral_token_account,
+                    lpcp_collateral_token_account,
+                    ucp_account,
+                    token_program,
+                    ucp_pda_account,
+                },
+                signed_to_u64(transfer_amount)?,
+                signer,
+            )?;
+        } else {
 
-                //Create and call instruction to burn iAsset from the AMM 
+            //Send collateral from the LPCP to the UCP
+
+            cpi::lpcp::send_collateral(
+                &oracle_info.lpcp_program_id,
+                cpi::lpcp::SendCollateralAccounts {
+                    authority: pda_account,
+                    lpcp_collateral_token_account,
+                    ucp_collateral_token_account,
+                    lpcp_account,
+                    token_program,
+                    lpcp_pda_account,
+                },
+                signed_to_u64(-transfer_amount)?,
+                signer,
+            )?;
+        }
 
-                let burn_iasset_from_amm = Instruction {
-                    program_id: amm_program_id,
-                    accounts,
-                    data,
-                };
-        
-                invoke_signed(
-                    &burn_iasset_from_amm,
-                    &[
-                        pda_fee_token_account.clone(),
-                        amm_iasset_token_account.clone(),
-                        iasset_mint_account.clone(),
-                        current_amm.clone(),
-                        token_program.clone(),
-                        amm_pda_account.clone(),
-                    ],
-                    &[&[&b"incept"[..], &[bump_seed]]],
-                )?;
-                
-            }    
 
-            //Recalculate LPR and add to total amount that will need to be transfered between UCP and LPCP
 
-            let usdi_put_in_after_correction = calc_sell_price_from_num_iasset(amm_usdi_token_account_info.amount, amm_iasset_token_account_info.amount, user_owned_iasset); 
-            let current_lpr_usdi_after_correction = (amm_usdi_token_account_info.amount - usdi_put_in_after_correction) as f64;  
-            transfer_amount += current_lpr_usdi - current_lpr_usdi_after_correction;
+        //Pay the keeper for a correction that changed state, at most once per reward interval
+
+        let next_reward_slot = oracle_info.last_keeper_reward_slot.saturating_add(oracle_info.keeper_reward_interval_slots);
+        let changed_state = changed_supply || transfer_amount != 0;
+        if !changed_state {
+            msg!("Correction changed nothing, no keeper reward");
+        } else if oracle_info.keeper_reward > 0 && current_slot >= next_reward_slot {
+            let fee_balance = TokenAccount::unpack(&pda_fee_token_account.data.borrow())?.amount;
+            let keeper_reward = oracle_info.keeper_reward.min(fee_balance);
+            if keeper_reward > 0 {
+                msg!("Paying keeper reward of {}", keeper_reward);
+                transfer_from_fee_account(
+                    keeper_reward,
+                    pda_fee_token_account,
+                    keeper_reward_token_account,
+                    pda_account,
+                    token_program,
+                    signer,
+                )?;
+
+                oracle_info.last_keeper_reward_slot = current_slot;
+                Oracle::pack(oracle_info, &mut oracle_account.data.borrow_mut())?;
+            }
         }
 
-        if transfer_amount > 0.0{
+        Ok(())
+    }
+}
 
-            //Set up program id, accounts, and instruction data to call SendCollateralLPCP UCP instruction
 
-            let ucp_program_id = oracle_info.ucp_program_id;
 
-            let mut accounts = Vec::with_capacity(6);
-            accounts.push(AccountMeta::new(*pda_fee_token_account.key, true));
-            accounts.push(AccountMeta::new(*ucp_collateral_token_account.key, true));
-            accounts.push(AccountMeta::new(*lpcp_collateral_token_account.key, false)); 
-            accounts.push(AccountMeta::new(*ucp_account.key, false));  
-            accounts.push(AccountMeta::new_readonly(*token_program.key, false)); 
-            accounts.push(AccountMeta::new_readonly(*ucp_pda_account.key, false));
+/// Loads the price from the Oracle's configured Pyth feed, rejecting any other
+/// account or an account not owned by the configured Pyth program
+fn load_pyth_price(
+    oracle_info: &Oracle,
+    pyth_product_info: &AccountInfo,
+    pyth_price_info: &AccountInfo,
+) -> Result<OraclePrice, ProgramError> {
+    if *pyth_product_info.key != oracle_info.pyth_product_pubkey || *pyth_price_info.key != oracle_info.pyth_price_pubkey {
+        msg!("Pyth accounts provided are not the Oracle's price feed");
+        return Err(OracleError::InvalidOraclePrice.into());
+    }
+    if *pyth_product_info.owner != oracle_info.pyth_program_id || *pyth_price_info.owner != oracle_info.pyth_program_id {
+        msg!("Pyth accounts provided are not owned by the Pyth program");
+        return Err(ProgramError::IncorrectProgramId);
+    }
+    parse_pyth_price(pyth_product_info, pyth_price_info)
+}
 
-            let mut data: Vec<u8> = Vec::with_capacity(5);
-            data.push(3);
-            let transfer_amount_u64 = to_u64(transfer_amount);
-            data.extend_from_slice(&transfer_amount_u64.to_le_bytes());
+fn parse_pyth_price(
+    pyth_product_info: &AccountInfo,
+    pyth_price_info: &AccountInfo,
+) -> Result<OraclePrice, ProgramError> {
+    let pyth_product_data = &pyth_product_info.try_borrow_data()?;
+    if pyth_product_data.len() < size_of::<pyth_client::Product>() {
+        msg!("Pyth product account provided is too small");
+        return Err(ProgramError::InvalidArgument);
+    }
+    let pyth_product = pyth_client::cast::<pyth_client::Product>(pyth_product_data);
 
+    //Checks for pyth magic number
+    if pyth_product.magic != pyth_client::MAGIC {
+        msg!("Pyth product account provided is not valid Pyth acccount");
+        return Err(ProgramError::InvalidArgument);
+    }
+    if pyth_product.atype != pyth_client::AccountType::Product as u32 {
+        msg!("Pyth product account provided is not a valid Pyth product account");
+        return Err(ProgramError::InvalidArgument);
+    }
 
+    if pyth_product.ver != pyth_client::VERSION_2 {
+        msg!("Pyth product account provided has a different version than the Pyth client");
+        return Err(ProgramError::InvalidArgument);
+    }
 
-            //Create and call instruction to send collateral from the UCP to the LPCP
+    if !pyth_product.px_acc.is_valid() {
+        msg!("Pyth product price account is invalid");
+        return Err(ProgramError::InvalidArgument);
+    }
 
-            let send_collateral_to_lpcp = Instruction {
-                program_id: ucp_program_id,
-                accounts,
-                data,
-            };
-        
-            invoke_signed(
-                &send_collateral_to_lpcp,
-                &[
-                    pda_fee_token_account.clone(),
-                    ucp_collateral_token_account.clone(),
-                    lpcp_collateral_token_account.clone(),
-                    ucp_account.clone(),
-                    token_program.clone(),
-                    ucp_pda_account.clone(),
-                ],
-                &[&[&b"incept"[..], &[bump_seed]]],
-            )?;
-            
-            
-        } else {
+    let pyth_price_pubkey = Pubkey::new_from_array(pyth_product.px_acc.val);
+    if &pyth_price_pubkey != pyth_price_info.key {
+        msg!("Pyth product price account does not match the Pyth price provided");
+        return Err(ProgramError::InvalidArgument);
+    }
 
-            //Set up program id, accounts, and instruction data to call SendCollateraUCP LPCP instruction
+    let pyth_price_data = &pyth_price_info.try_borrow_data()?;
+    if pyth_price_data.len() < size_of::<pyth_client::Price>() {
+        msg!("Pyth price account provided is too small");
+        return Err(ProgramError::InvalidArgument);
+    }
+    let pyth_price = pyth_client::cast::<pyth_client::Price>(pyth_price_data);
 
-            let lpcp_program_id = oracle_info.lpcp_program_id;
+    //Checks the price account itself before trusting its aggregate
+    if pyth_price.magic != pyth_client::MAGIC {
+        msg!("Pyth price account provided is not valid Pyth acccount");
+        return Err(ProgramError::InvalidArgument);
+    }
+    if pyth_price.atype != pyth_client::AccountType::Price as u32 {
+        msg!("Pyth price account provided is not a valid Pyth price account");
+        return Err(ProgramError::InvalidArgument);
+    }
+    if pyth_price.ver != pyth_client::VERSION_2 {
+        msg!("Pyth price account provided has a different version than the Pyth client");
+        return Err(ProgramError::InvalidArgument);
+    }
 
-            let mut accounts = Vec::with_capacity(6);
-            accounts.push(AccountMeta::new(*pda_fee_token_account.key, true));
-            accounts.push(AccountMeta::new(*lpcp_collateral_token_account.key, true));
-            accounts.push(AccountMeta::new(*ucp_collateral_token_account.key, false)); 
-            accounts.push(AccountMeta::new(*lpcp_account.key, false));  
-            accounts.push(AccountMeta::new_readonly(*token_program.key, false)); 
-            accounts.push(AccountMeta::new_readonly(*lpcp_pda_account.key, false));
+    msg!(" price account .. {:?}", pyth_price_info.key);
+    msg!(" price type ... {}", get_price_type(&pyth_price.ptype));
+    msg!(" status .... {}", get_status(&pyth_price.agg.status));
+    msg!(" corp act .... {}", get_corp_act(&pyth_price.agg.corp_act));
 
-            let mut data: Vec<u8> = Vec::with_capacity(5);
-            data.push(3);
-            let transfer_amount_u64 = to_u64(transfer_amount);
-            data.extend_from_slice(&transfer_amount_u64.to_le_bytes());
+    if !matches!(pyth_price.agg.status, PriceStatus::Trading) {
+        msg!("Pyth price is not currently trading");
+        return Err(OracleError::InvalidOraclePrice.into());
+    }
+    if pyth_price.agg.price <= 0 {
+        msg!("Pyth price {} is not positive", pyth_price.agg.price);
+        return Err(OracleError::InvalidOraclePrice.into());
+    }
 
+    let oracle_price = OraclePrice {
+        price: normalize_pyth_value(pyth_price.agg.price as u64, pyth_price.expo)?,
+        conf: normalize_pyth_value(pyth_price.agg.conf, pyth_price.expo)?,
+        publish_slot: pyth_price.agg.pub_slot,
+    };
 
+    msg!(" price ....{}", oracle_price.price);
+    msg!(" conf .... {}", oracle_price.conf);
+    msg!(" publish slot .... {}", oracle_price.publish_slot);
 
-            //Create and call instruction to send collateral from the UCP to the LPCP
+    Ok(oracle_price)
+}
 
-            let send_collateral_to_lpcp = Instruction {
-                program_id: lpcp_program_id,
-                accounts,
-                data,
-            };
-        
-            invoke_signed(
-                &send_collateral_to_lpcp,
-                &[
-                    pda_fee_token_account.clone(),
-                    ucp_collateral_token_account.clone(),
-                    lpcp_collateral_token_account.clone(),
-                    ucp_account.clone(),
-                    token_program.clone(),
-                    ucp_pda_account.clone(),
-                ],
-                &[&[&b"incept"[..], &[bump_seed]]],
-            )?;
-        }   
+fn load_oracle_price(
+    oracle_info: &Oracle,
+    pyth_product_info: &AccountInfo,
+    pyth_price_info: &AccountInfo,
+) -> Result<Decimal, ProgramError> {
+    let oracle_price = load_pyth_price(oracle_info, pyth_product_info, pyth_price_info)?;
+    oracle_price_to_decimal(&oracle_price)
+}
 
-        Ok(())
+fn oracle_price_to_decimal(oracle_price: &OraclePrice) -> Result<Decimal, ProgramError> {
+    Decimal::from_fixed(oracle_price.price, OraclePrice::DECIMALS)
+}
+
+/// Rescales a Pyth value with exponent `expo` to `OraclePrice::DECIMALS` decimals
+fn normalize_pyth_value(
+    value: u64,
+    expo: i32,
+) -> Result<u64, ProgramError> {
+    let scale = expo + OraclePrice::DECIMALS as i32;
+    let factor = 10u64.checked_pow(scale.unsigned_abs()).ok_or(OracleError::InvalidOraclePrice)?;
+    let normalized = if scale >= 0 {
+        value.checked_mul(factor)
+    } else {
+        Some(value / factor)
+    };
+    normalized.ok_or_else(|| {
+        msg!("Pyth value {} with exponent {} does not fit the normalized price", value, expo);
+        OracleError::InvalidOraclePrice.into()
+    })
+}
+
+/// Refuses a trade if the AMM price deviates from the oracle price by more than the
+/// Oracle's band; a band of zero is disabled
+///
+/// While the band is enabled, {band_accounts} must start with the Oracle's Pyth product
+/// account, followed by its Pyth price account and the USDI and iAsset mints
+fn check_oracle_band(
+    oracle_info: &Oracle,
+    band_accounts: &[AccountInfo],
+    amm_usdi_token_account: &AccountInfo,
+    amm_iasset_token_account: &AccountInfo,
+) -> ProgramResult {
+    if oracle_info.oracle_band_bps == 0 {
+        return Ok(());
     }
+    let (pyth_product_info, pyth_price_info, usdi_mint_info, iasset_mint_info) = match band_accounts {
+        [pyth_product_info, pyth_price_info, usdi_mint_info, iasset_mint_info, ..] => (pyth_product_info, pyth_price_info, usdi_mint_info, iasset_mint_info),
+        _ => {
+            msg!("Oracle band accounts are required while the band is enabled");
+            return Err(ProgramError::NotEnoughAccountKeys);
+        }
+    };
+    let oracle_price = load_oracle_price(oracle_info, pyth_product_info, pyth_price_info)?;
+    let (usdi_amm_amount, usdi_decimals) = load_token_amount_and_decimals(amm_usdi_token_account, usdi_mint_info)?;
+    let (iasset_amm_amount, iasset_decimals) = load_token_amount_and_decimals(amm_iasset_token_account, iasset_mint_info)?;
+    if iasset_amm_amount == 0 || oracle_price.is_zero() {
+        msg!("AMM price cannot be compared to oracle price {}", oracle_price);
+        return Err(OracleError::PriceOutsideOracleBand.into());
+    }
+
+    let amm_price = calc_amm_price(usdi_amm_amount, iasset_amm_amount, usdi_decimals, iasset_decimals)?;
+    let deviation = if amm_price > oracle_price {
+        amm_price.try_sub(oracle_price)?
+    } else {
+        oracle_price.try_sub(amm_price)?
+    };
+    let deviation_bps = deviation.try_mul_u64(10_000)?.try_div(oracle_price)?;
+    if deviation_bps > Decimal::from(oracle_info.oracle_band_bps) {
+        msg!("AMM price {} deviates from oracle price {} by {} bps", amm_price, oracle_price, deviation_bps);
+        return Err(OracleError::PriceOutsideOracleBand.into());
+    }
+    Ok(())
 }
 
+/// Returns the balance of {token_account} and the decimals of its mint, {mint_info}
+fn load_token_amount_and_decimals(
+    token_account: &AccountInfo,
+    mint_info: &AccountInfo,
+) -> Result<(u64, u32), ProgramError> {
+    let token_account_info = TokenAccount::unpack(&token_account.data.borrow())?;
+    if token_account_info.mint != *mint_info.key {
+        msg!("Mint {} does not match token account {}", mint_info.key, token_account.key);
+        return Err(OracleError::InvalidMintData.into());
+    }
+    let decimals = u32::from(Mint::unpack(&mint_info.data.borrow())?.decimals);
+    Ok((token_account_info.amount, decimals))
+}
 
+/// Returns the AMM price in whole USDI per whole iAsset, so it compares with the oracle
+/// price whatever the mints' decimals
+fn calc_amm_price(
+    usdi_amm_amount: u64,
+    iasset_amm_amount: u64,
+    usdi_decimals: u32,
+    iasset_decimals: u32,
+) -> Result<Decimal, ProgramError> {
+    Decimal::from_fixed(usdi_amm_amount, usdi_decimals)?.try_div(Decimal::from_fixed(iasset_amm_amount, iasset_decimals)?)
+}
 
 fn get_price_type(ptype: &PriceType) -> &'static str {
     match ptype {
@@ -716,30 +1313,309 @@ fn get_corp_act(cact: &CorpAction) -> &'static str {
     }
 }
 
-fn calc_sell_price_from_num_iasset(
+fn transfer_from_fee_account<'a>(
+    amount: u64,
+    pda_fee_token_account: &AccountInfo<'a>,
+    destination_token_account: &AccountInfo<'a>,
+    pda_account: &AccountInfo<'a>,
+    token_program: &AccountInfo<'a>,
+    signer: cpi::OracleSigner,
+) -> ProgramResult {
+    let transfer_ix = spl_token::instruction::transfer(
+        token_program.key,
+        pda_fee_token_account.key,
+        destination_token_account.key,
+        pda_account.key,
+        &[],
+        amount,
+    )?;
+
+    signer.invoke_signed(
+        &transfer_ix,
+        &[
+            pda_fee_token_account.clone(),
+            destination_token_account.clone(),
+            pda_account.clone(),
+            token_program.clone(),
+        ],
+    )
+}
+
+/// Returns the curve config set for {amm_account} by the Oracle admin
+fn load_curve_config(
+    curve_config_account: &AccountInfo,
+    oracle_account: &AccountInfo,
+    amm_account: &AccountInfo,
+    program_id: &Pubkey,
+) -> Result<CurveConfig, ProgramError> {
+    if curve_config_account.owner != program_id {
+        return Err(ProgramError::IncorrectProgramId);
+    }
+    let curve_config = CurveConfig::unpack(&curve_config_account.data.borrow())?;
+    if curve_config.oracle_pubkey != *oracle_account.key || curve_config.amm_pubkey != *amm_account.key {
+        msg!("Curve config belongs to another Oracle or AMM");
+        return Err(OracleError::InvalidCurve.into());
+    }
+    Ok(curve_config)
+}
+
+fn check_oracle_pda(
+    signer: &cpi::OracleSigner,
+    pda_account: &AccountInfo,
+    program_id: &Pubkey,
+) -> ProgramResult {
+    if signer.address(program_id)? != *pda_account.key {
+        msg!("Oracle PDA account {} does not belong to Oracle {}", pda_account.key, signer.oracle_key);
+        return Err(ProgramError::InvalidSeeds);
+    }
+    Ok(())
+}
+
+fn calc_max_correction(
+    oracle_info: &Oracle,
+    iasset_amm_amount: u64,
+) -> Result<Option<Decimal>, ProgramError> {
+    let mut max_correction = None;
+    if oracle_info.max_correction_amount > 0 {
+        max_correction = Some(Decimal::from(oracle_info.max_correction_amount));
+    }
+    if oracle_info.max_correction_bps > 0 {
+        let bps_cap = Decimal::from(iasset_amm_amount)
+            .try_mul_u64(oracle_info.max_correction_bps)?
+            .try_div_u64(10_000)?;
+        max_correction = Some(max_correction.map_or(bps_cap, |cap: Decimal| cap.min(bps_cap)));
+    }
+    Ok(max_correction)
+}
+
+fn check_correction_interval(
+    oracle_info: &Oracle,
+    curve_config: &CurveConfig,
+    current_slot: u64,
+    oracle_price: u64,
+) -> ProgramResult {
+    let next_correction_slot = curve_config.last_correction_slot.saturating_add(oracle_info.min_correction_interval_slots);
+    if current_slot >= next_correction_slot || curve_config.last_correction_price == 0 {
+        return Ok(());
+    }
+
+    let last_price = curve_config.last_correction_price as u128;
+    let price_move_bps = checked_mul((oracle_price as u128).abs_diff(last_price), 10_000)? / last_price;
+    if oracle_info.correction_override_bps > 0 && price_move_bps > oracle_info.correction_override_bps as u128 {
+        msg!("Price moved {} bps since the last correction, skipping the interval", price_move_bps);
+        return Ok(());
+    }
+
+    msg!("Last correction of AMM {} was at slot {}, next allowed at slot {}", curve_config.amm_pubkey, curve_config.last_correction_slot, next_correction_slot);
+    Err(OracleError::CorrectionTooFrequent.into())
+}
+
+fn check_not_paused(oracle_info: &Oracle, flag: u8) -> ProgramResult {
+    if oracle_info.is_paused(flag) {
+        msg!("Instruction is paused, paused flags are {:#04x}", oracle_info.paused_flags);
+        return Err(OracleError::Paused.into());
+    }
+    Ok(())
+}
+
+fn check_expiry(expiry_slot: Option<u64>) -> ProgramResult {
+    if let Some(expiry_slot) = expiry_slot {
+        let current_slot = Clock::get()?.slot;
+        if current_slot > expiry_slot {
+            msg!("Trade expired at slot {}, current slot is {}", expiry_slot, current_slot);
+            return Err(OracleError::TradeExpired.into());
+        }
+    }
+    Ok(())
+}
+
+fn calc_trade_fee(
+    usdi_amount: u64,
+    trade_fee_bps: u64,
+) -> Result<u64, ProgramError> {
+    //Rounded up, so the fee token account never loses dust
+    let trade_fee = checked_mul(usdi_amount as u128, trade_fee_bps as u128)?.div_ceil(10_000);
+    u64::try_from(trade_fee).map_err(|_| {
+        msg!("Math overflow: trade fee {} does not fit in u64", trade_fee);
+        OracleError::MathOverflow.into()
+    })
+}
+
+fn collect_trade_fee<'a>(
+    trade_fee: u64,
+    user_usdi_token_account: &AccountInfo<'a>,
+    pda_fee_token_account: &AccountInfo<'a>,
+    user_account: &AccountInfo<'a>,
+    token_program: &AccountInfo<'a>,
+) -> ProgramResult {
+    if trade_fee == 0 {
+        return Ok(());
+    }
+
+    let transfer_fee_ix = spl_token::instruction::transfer(
+        token_program.key,
+        user_usdi_token_account.key,
+        pda_fee_token_account.key,
+        user_account.key,
+        &[],
+        trade_fee,
+    )?;
+
+    invoke(
+        &transfer_fee_ix,
+        &[
+            user_usdi_token_account.clone(),
+            pda_fee_token_account.clone(),
+            user_account.clone(),
+            token_program.clone(),
+        ],
+    )
+}
+
+fn check_slippage(
+    amount_out: u64,
+    min_amount_out: u64,
+) -> ProgramResult {
+    if amount_out < min_amount_out {
+        msg!("Trade returned {} tokens, less than the minimum of {}", amount_out, min_amount_out);
+        return Err(OracleError::SlippageExceeded.into());
+    }
+    Ok(())
+}
+
+/// Returns the preferred LPR for {oracle_price}
+pub fn calc_lpr(
+    oracle_price: Decimal,
+) -> Result<Decimal, ProgramError> {
+    oracle_price.try_mul(Decimal::from_bps(9_000))
+}
+
+/// AMM balances and mint decimals a collateral correction is computed from
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct AmmBalances {
+    pub usdi_amount: u64,
+    pub iasset_amount: u64,
+    pub iasset_supply: u64,
+    pub usdi_decimals: u32,
+    pub iasset_decimals: u32,
+}
+
+/// Direction and size of one AMM's collateral correction
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Correction {
+    /// Mint iAsset to the AMM if set, burn it from the AMM otherwise
+    pub mint_iasset: bool,
+    /// iAsset to mint or burn, in base units
+    pub amount: u64,
+    /// iAsset held outside the AMM
+    pub user_owned_iasset: u64,
+    /// The AMM's LPR before the correction, in USDI base units, rounded down
+    pub lpr_usdi: u64,
+}
+
+/// Returns the USDI an AMM holding {usdi_amm_amount} and {iasset_amm_amount} keeps
+/// once {user_owned_iasset} is sold back into it, rounded in the given direction
+pub fn calc_lpr_usdi(
+    curve: &dyn Curve,
     usdi_amm_amount: u64,
     iasset_amm_amount: u64,
-    iasset_purchase_amount: u64,
-) -> u64 {
-    let mut delta_x = 0.0;
-    let delta_y = 100.0;
-    let mut x = usdi_amm_amount as f64;
-    let mut y = iasset_amm_amount as f64;
-
-    while (iasset_amm_amount as f64) - y > -(iasset_purchase_amount as f64) {
-        delta_x = (2.0*delta_y*y*x + delta_y*delta_y*x)/(y*(2.0*y + 3.0*delta_y));
-        x -= delta_x;
-        y += delta_y;
-    }
-    return to_u64((usdi_amm_amount as f64) - x);
+    user_owned_iasset: u64,
+    usdi_decimals: u32,
+    iasset_decimals: u32,
+    rounding: Rounding,
+) -> Result<u64, ProgramError> {
+    //The USDI paid out for the iAsset rounds the other way from what the AMM keeps
+    let usdi_out_rounding = match rounding {
+        Rounding::RoundDown => Rounding::RoundUp,
+        Rounding::RoundUp => Rounding::RoundDown,
+    };
+    let decimals = SwapDecimals::new(usdi_decimals, iasset_decimals);
+    let usdi_put_in = curve.sell_price_rounded(usdi_amm_amount, iasset_amm_amount, user_owned_iasset, decimals, usdi_out_rounding)?;
+    checked_sub(usdi_amm_amount, usdi_put_in)
+}
+
+/// Returns the iAsset to mint to or burn from an AMM so its LPR moves toward the
+/// preferred LPR for {oracle_price}
+///
+/// The LPR is compared in whole USDI and iAsset so the oracle price applies, and the
+/// result is capped by the Oracle's correction limits
+pub fn calc_correction(
+    oracle_info: &Oracle,
+    curve: &dyn Curve,
+    oracle_price: Decimal,
+    balances: &AmmBalances,
+) -> Result<Correction, ProgramError> {
+    let user_owned_iasset = checked_sub(balances.iasset_supply, balances.iasset_amount)?;
+    let lpr_usdi = calc_lpr_usdi(
+        curve,
+        balances.usdi_amount,
+        balances.iasset_amount,
+        user_owned_iasset,
+        balances.usdi_decimals,
+        balances.iasset_decimals,
+        Rounding::RoundDown,
+    )?;
+    let current_lpr = Decimal::from_fixed(lpr_usdi, balances.usdi_decimals)?;
+    let preferred_lpr = calc_lpr(oracle_price)?;
+    let preferred_lpr_squared = preferred_lpr.try_mul(preferred_lpr)?;
+
+    let mint_iasset = current_lpr > preferred_lpr_squared;
+    let amount = if mint_iasset {
+        current_lpr.try_sub(preferred_lpr_squared)?
+    } else {
+        preferred_lpr_squared.try_sub(current_lpr)?
+    }
+    .try_div(preferred_lpr)?
+    .try_to_base_units(balances.iasset_decimals)?;
+
+    //Cap the correction so large price moves are applied over several runs
+    let amount = match calc_max_correction(oracle_info, balances.iasset_amount)? {
+        Some(max_correction) => amount.min(max_correction),
+        None => amount,
+    };
+
+    //Minted iAsset rounds down and burned iAsset rounds up, so the supply backed by the
+    //UCP never grows by a dust unit more than the correction calls for
+    let rounding = if mint_iasset { Rounding::RoundDown } else { Rounding::RoundUp };
+
+    Ok(Correction {
+        mint_iasset,
+        amount: to_u64(amount, rounding)?,
+        user_owned_iasset,
+        lpr_usdi,
+    })
 }
 
-fn calc_lpr(
-    oracle_price: f64,
-) -> f64 {
-    return oracle_price*0.9;
+/// Converts {value} to a token amount, rounding in the given direction
+///
+/// Amounts leaving a protocol-held pool round down and amounts owed to one round up,
+/// so the pools never lose dust
+pub fn to_u64(value: Decimal, rounding: Rounding) -> Result<u64, ProgramError> {
+    value.try_to_u64(rounding)
 }
 
-pub fn to_u64(value: f64) -> u64 {
-    return value.abs().round() as u64;
-}
\ No newline at end of file
+/// Converts a signed token amount to u64, failing instead of flipping the sign of negative input
+pub fn signed_to_u64(value: i128) -> Result<u64, ProgramError> {
+    if value < 0 {
+        msg!("Amount {} is negative", value);
+        return Err(OracleError::NegativeAmount.into());
+    }
+    u64::try_from(value).map_err(|_| {
+        msg!("Math overflow: {} does not fit in u64", value);
+        OracleError::MathOverflow.into()
+    })
+}
+
+fn checked_sub(a: u64, b: u64) -> Result<u64, ProgramError> {
+    a.checked_sub(b).ok_or_else(|| {
+        msg!("Math underflow: {} - {}", a, b);
+        OracleError::MathUnderflow.into()
+    })
+}
+
+fn checked_mul(a: u128, b: u128) -> Result<u128, ProgramError> {
+    a.checked_mul(b).ok_or_else(|| {
+        msg!("Math overflow: {} * {}", a, b);
+        OracleError::MathOverflow.into()
+    })
+}
@@ -0,0 +1,311 @@
+//! Off-chain replay of collateral corrections against simulated balances.
+//!
+//! Reads a CSV of events, one per line, and runs the same `calc_correction` and
+//! `calc_lpr_usdi` math as CollateralCorrection at every price tick:
+//!
+//! ```text
+//! # kind, values...
+//! pool,<usdi amount>,<iasset amount>,<iasset supply>,<usdi decimals>,<iasset decimals>
+//! curve,<curve type>,<amplification>
+//! cap,<max correction amount>,<max correction bps>
+//! tick,<oracle price>
+//! buy,<usdi amount sold to the AMM>
+//! sell,<iasset amount sold to the AMM>
+//! ```
+//!
+//! Amounts are in base units and prices in whole USDI per iAsset. `pool` must come
+//! first; the curve defaults to the constant product and the correction caps to
+//! none. Every tick runs a correction, ignoring the Oracle's minimum correction
+//! interval.
+//!
+//! Trades fill at the bare curve price: neither the Oracle's trade fee nor any fee
+//! or spread the AMM charges on top is modelled, so a replay's balances drift from
+//! the program's by what those would have collected.
+
+use std::{convert::TryFrom, fmt, str::FromStr};
+
+use solana_program::{program_error::ProgramError, program_pack::Pack};
+use thiserror::Error;
+
+use crate::{
+    curve::{curve_from_config, Curve, SwapDecimals},
+    error::OracleError,
+    math::{Decimal, Rounding},
+    processor::{calc_correction, calc_lpr, calc_lpr_usdi, AmmBalances},
+    states::Oracle,
+};
+
+/// Column names of the rows produced by `StepReport`'s `Display`
+pub const REPORT_HEADER: &str =
+    "step,price,target_lpr,lpr_before,lpr_after,lpr_drift,minted,burned,ucp_to_lpcp,lpcp_to_ucp";
+
+#[derive(Debug, Error, PartialEq)]
+pub enum SimulatorError {
+    /// A CSV line could not be parsed
+    #[error("line {line}: {message}")]
+    Parse { line: usize, message: String },
+
+    /// The program math rejected an event
+    #[error("line {line}: {error}")]
+    Program { line: usize, error: ProgramError },
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Event {
+    Pool(AmmBalances),
+    Curve { curve_type: u8, amplification: u64 },
+    Cap { max_correction_amount: u64, max_correction_bps: u64 },
+    Tick { price: Decimal },
+    Buy { usdi_amount: u64 },
+    Sell { iasset_amount: u64 },
+}
+
+/// One price tick's correction, with LPRs in whole USDI
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct StepReport {
+    pub step: usize,
+    pub price: Decimal,
+    pub target_lpr: Decimal,
+    pub lpr_before: Decimal,
+    pub lpr_after: Decimal,
+    pub minted: u64,
+    pub burned: u64,
+    pub ucp_to_lpcp: u64,
+    pub lpcp_to_ucp: u64,
+}
+
+impl fmt::Display for StepReport {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let drift = if self.lpr_after >= self.target_lpr {
+            format!("{}", Decimal(self.lpr_after.0 - self.target_lpr.0))
+        } else {
+            format!("-{}", Decimal(self.target_lpr.0 - self.lpr_after.0))
+        };
+        write!(
+            f,
+            "{},{},{},{},{},{},{},{},{},{}",
+            self.step,
+            self.price,
+            self.target_lpr,
+            self.lpr_before,
+            self.lpr_after,
+            drift,
+            self.minted,
+            self.burned,
+            self.ucp_to_lpcp,
+            self.lpcp_to_ucp
+        )
+    }
+}
+
+/// Simulated AMM, UCP and LPCP balances
+pub struct Simulation {
+    oracle_info: Oracle,
+    curve: Box<dyn Curve>,
+    pub balances: AmmBalances,
+    /// Net collateral the UCP has received, in USDI base units
+    pub ucp_collateral: i128,
+    /// Net collateral the LPCP has received, in USDI base units
+    pub lpcp_collateral: i128,
+    steps: usize,
+}
+
+impl Simulation {
+    pub fn new(balances: AmmBalances) -> Result<Self, ProgramError> {
+        Ok(Self {
+            oracle_info: Oracle::unpack_unchecked(&[0; Oracle::LEN])?,
+            curve: curve_from_config(0, 0)?,
+            balances,
+            ucp_collateral: 0,
+            lpcp_collateral: 0,
+            steps: 0,
+        })
+    }
+
+    pub fn set_curve(&mut self, curve_type: u8, amplification: u64) -> Result<(), ProgramError> {
+        self.curve = curve_from_config(curve_type, amplification)?;
+        Ok(())
+    }
+
+    pub fn set_caps(&mut self, max_correction_amount: u64, max_correction_bps: u64) {
+        self.oracle_info.max_correction_amount = max_correction_amount;
+        self.oracle_info.max_correction_bps = max_correction_bps;
+    }
+
+    /// Sells {usdi_amount} USDI into the AMM for iAsset
+    pub fn buy(&mut self, usdi_amount: u64) -> Result<(), ProgramError> {
+        let decimals = SwapDecimals::new(self.balances.iasset_decimals, self.balances.usdi_decimals);
+        let iasset_out = self.curve.sell_price(self.balances.iasset_amount, self.balances.usdi_amount, usdi_amount, decimals)?;
+        let usdi_amount = checked_add(self.balances.usdi_amount, usdi_amount)?;
+        let iasset_amount = checked_sub(self.balances.iasset_amount, iasset_out)?;
+        self.balances = AmmBalances { usdi_amount, iasset_amount, ..self.balances };
+        Ok(())
+    }
+
+    /// Sells {iasset_amount} user-owned iAsset into the AMM for USDI
+    pub fn sell(&mut self, iasset_amount: u64) -> Result<(), ProgramError> {
+        let user_owned_iasset = checked_sub(self.balances.iasset_supply, self.balances.iasset_amount)?;
+        checked_sub(user_owned_iasset, iasset_amount)?;
+        let decimals = SwapDecimals::new(self.balances.usdi_decimals, self.balances.iasset_decimals);
+        let usdi_out = self.curve.sell_price(self.balances.usdi_amount, self.balances.iasset_amount, iasset_amount, decimals)?;
+        let iasset_amount = checked_add(self.balances.iasset_amount, iasset_amount)?;
+        let usdi_amount = checked_sub(self.balances.usdi_amount, usdi_out)?;
+        self.balances = AmmBalances { usdi_amount, iasset_amount, ..self.balances };
+        Ok(())
+    }
+
+    /// Runs a correction at {price}, as CollateralCorrection would for a single AMM
+    pub fn tick(&mut self, price: Decimal) -> Result<StepReport, ProgramError> {
+        let correction = calc_correction(&self.oracle_info, self.curve.as_ref(), price, &self.balances)?;
+        let (minted, burned, balances) = if correction.mint_iasset {
+            let iasset_amount = checked_add(self.balances.iasset_amount, correction.amount)?;
+            let iasset_supply = checked_add(self.balances.iasset_supply, correction.amount)?;
+            (correction.amount, 0, AmmBalances { iasset_amount, iasset_supply, ..self.balances })
+        } else {
+            let iasset_amount = checked_sub(self.balances.iasset_amount, correction.amount)?;
+            let iasset_supply = checked_sub(self.balances.iasset_supply, correction.amount)?;
+            (0, correction.amount, AmmBalances { iasset_amount, iasset_supply, ..self.balances })
+        };
+
+        //Like the program, only an AMM whose balance changed moves collateral
+        let lpr_usdi_after = if correction.amount == 0 {
+            correction.lpr_usdi
+        } else {
+            calc_lpr_usdi(
+                self.curve.as_ref(),
+                balances.usdi_amount,
+                balances.iasset_amount,
+                correction.user_owned_iasset,
+                balances.usdi_decimals,
+                balances.iasset_decimals,
+                Rounding::RoundUp,
+            )?
+        };
+        let transfer_amount = correction.lpr_usdi as i128 - lpr_usdi_after as i128;
+        let preferred_lpr = calc_lpr(price)?;
+        let report = StepReport {
+            step: self.steps + 1,
+            price,
+            target_lpr: preferred_lpr.try_mul(preferred_lpr)?,
+            lpr_before: Decimal::from_fixed(correction.lpr_usdi, balances.usdi_decimals)?,
+            lpr_after: Decimal::from_fixed(lpr_usdi_after, balances.usdi_decimals)?,
+            minted,
+            burned,
+            ucp_to_lpcp: transfer_amount.max(0) as u64,
+            lpcp_to_ucp: (-transfer_amount).max(0) as u64,
+        };
+
+        //Only apply the correction once every step has succeeded, like a failed transaction
+        self.balances = balances;
+        self.ucp_collateral -= transfer_amount;
+        self.lpcp_collateral += transfer_amount;
+        self.steps = report.step;
+        Ok(report)
+    }
+}
+
+/// Parses the simulator CSV into events, paired with their 1-based line numbers
+pub fn parse_events(csv: &str) -> Result<Vec<(usize, Event)>, SimulatorError> {
+    let mut events = Vec::new();
+    for (index, line) in csv.lines().enumerate() {
+        let line_number = index + 1;
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+        let fields: Vec<&str> = line.split(',').map(str::trim).collect();
+        let parse_error = |message: String| SimulatorError::Parse { line: line_number, message };
+        let expect_fields = |count: usize| {
+            if fields.len() == count + 1 {
+                Ok(())
+            } else {
+                Err(parse_error(format!("`{}` expects {} values, found {}", fields[0], count, fields.len() - 1)))
+            }
+        };
+        let value = |index: usize| {
+            fields[index]
+                .parse::<u64>()
+                .map_err(|_| parse_error(format!("invalid amount `{}`", fields[index])))
+        };
+
+        let decimals = |index: usize| {
+            u32::try_from(value(index)?).map_err(|_| parse_error(format!("invalid decimals `{}`", fields[index])))
+        };
+
+        let event = match fields[0] {
+            "pool" => {
+                expect_fields(5)?;
+                Event::Pool(AmmBalances {
+                    usdi_amount: value(1)?,
+                    iasset_amount: value(2)?,
+                    iasset_supply: value(3)?,
+                    usdi_decimals: decimals(4)?,
+                    iasset_decimals: decimals(5)?,
+                })
+            }
+            "curve" => {
+                expect_fields(2)?;
+                let curve_type = u8::try_from(value(1)?).map_err(|_| parse_error(format!("invalid curve type `{}`", fields[1])))?;
+                Event::Curve { curve_type, amplification: value(2)? }
+            }
+            "cap" => {
+                expect_fields(2)?;
+                Event::Cap { max_correction_amount: value(1)?, max_correction_bps: value(2)? }
+            }
+            "tick" => {
+                expect_fields(1)?;
+                let price = Decimal::from_str(fields[1]).map_err(|_| parse_error(format!("invalid price `{}`", fields[1])))?;
+                Event::Tick { price }
+            }
+            "buy" => {
+                expect_fields(1)?;
+                Event::Buy { usdi_amount: value(1)? }
+            }
+            "sell" => {
+                expect_fields(1)?;
+                Event::Sell { iasset_amount: value(1)? }
+            }
+            kind => return Err(parse_error(format!("unknown event `{}`", kind))),
+        };
+        events.push((line_number, event));
+    }
+    Ok(events)
+}
+
+/// Replays the simulator CSV, returning the report for each price tick
+pub fn run(csv: &str) -> Result<Vec<StepReport>, SimulatorError> {
+    let mut simulation = None;
+    let mut reports = Vec::new();
+
+    for (line, event) in parse_events(csv)? {
+        let program_error = |error: ProgramError| SimulatorError::Program { line, error };
+        match event {
+            Event::Pool(balances) => simulation = Some(Simulation::new(balances).map_err(program_error)?),
+            Event::Curve { curve_type, amplification } => started(&mut simulation, line)?
+                .set_curve(curve_type, amplification)
+                .map_err(program_error)?,
+            Event::Cap { max_correction_amount, max_correction_bps } => {
+                started(&mut simulation, line)?.set_caps(max_correction_amount, max_correction_bps)
+            }
+            Event::Tick { price } => reports.push(started(&mut simulation, line)?.tick(price).map_err(program_error)?),
+            Event::Buy { usdi_amount } => started(&mut simulation, line)?.buy(usdi_amount).map_err(program_error)?,
+            Event::Sell { iasset_amount } => started(&mut simulation, line)?.sell(iasset_amount).map_err(program_error)?,
+        }
+    }
+    Ok(reports)
+}
+
+fn started(simulation: &mut Option<Simulation>, line: usize) -> Result<&mut Simulation, SimulatorError> {
+    simulation.as_mut().ok_or_else(|| SimulatorError::Parse {
+        line,
+        message: "a `pool` row must come first".to_string(),
+    })
+}
+
+fn checked_add(a: u64, b: u64) -> Result<u64, ProgramError> {
+    a.checked_add(b).ok_or_else(|| OracleError::MathOverflow.into())
+}
+
+fn checked_sub(a: u64, b: u64) -> Result<u64, ProgramError> {
+    a.checked_sub(b).ok_or_else(|| OracleError::MathUnderflow.into())
+}
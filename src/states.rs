@@ -7,12 +7,46 @@ use solana_program::{
 
 use arrayref::{array_mut_ref, array_ref, array_refs, mut_array_refs};
 
+use crate::curve::{curve_from_config, Curve};
+
 pub struct Oracle {
     pub is_initialized: bool,
     pub fee_token_account_pubkey: Pubkey,
     pub amm_program_id: Pubkey,
     pub lpcp_program_id: Pubkey,
     pub ucp_program_id: Pubkey,
+    pub oracle_band_bps: u64,
+    pub admin_pubkey: Pubkey,
+    pub paused_flags: u8,
+    pub trade_fee_bps: u64,
+    pub keeper_reward: u64,
+    pub keeper_reward_interval_slots: u64,
+    pub last_keeper_reward_slot: u64,
+    pub min_correction_interval_slots: u64,
+    pub correction_override_bps: u64,
+    pub max_correction_amount: u64,
+    pub max_correction_bps: u64,
+    pub bump_seed: u8,
+    pub pyth_program_id: Pubkey,
+    pub pyth_product_pubkey: Pubkey,
+    pub pyth_price_pubkey: Pubkey,
+}
+
+impl Oracle {
+    /// Pauses TradeUSDI, TradeiAsset and TradeRoute
+    pub const PAUSE_TRADING: u8 = 1;
+    /// Pauses CollateralCorrection
+    pub const PAUSE_CORRECTION: u8 = 1 << 1;
+    /// Pauses Quote
+    pub const PAUSE_QUOTE: u8 = 1 << 2;
+    /// Pauses GetPrice
+    pub const PAUSE_GET_PRICE: u8 = 1 << 3;
+    /// Pauses WithdrawFees
+    pub const PAUSE_WITHDRAW_FEES: u8 = 1 << 4;
+
+    pub fn is_paused(&self, flag: u8) -> bool {
+        self.paused_flags & flag != 0
+    }
 }
 
 impl Sealed for Oracle {}
@@ -24,7 +58,7 @@ impl IsInitialized for Oracle {
 }
 
 impl Pack for Oracle {
-    const LEN: usize = 129;
+    const LEN: usize = 331;
     fn unpack_from_slice(src: &[u8]) -> Result<Self, ProgramError> {
         let src = array_ref![src, 0, Oracle::LEN];
         let (
@@ -33,7 +67,22 @@ impl Pack for Oracle {
             amm_program_id,
             lpcp_program_id,
             ucp_program_id,
-        ) = array_refs![src, 1, 32, 32, 32, 32];
+            oracle_band_bps,
+            admin_pubkey,
+            paused_flags,
+            trade_fee_bps,
+            keeper_reward,
+            keeper_reward_interval_slots,
+            last_keeper_reward_slot,
+            min_correction_interval_slots,
+            correction_override_bps,
+            max_correction_amount,
+            max_correction_bps,
+            bump_seed,
+            pyth_program_id,
+            pyth_product_pubkey,
+            pyth_price_pubkey,
+        ) = array_refs![src, 1, 32, 32, 32, 32, 8, 32, 1, 8, 8, 8, 8, 8, 8, 8, 8, 1, 32, 32, 32];
         let is_initialized = match is_initialized {
             [0] => false,
             [1] => true,
@@ -46,6 +95,21 @@ impl Pack for Oracle {
             amm_program_id: Pubkey::new_from_array(*amm_program_id),
             lpcp_program_id: Pubkey::new_from_array(*lpcp_program_id),
             ucp_program_id: Pubkey::new_from_array(*ucp_program_id),
+            oracle_band_bps: u64::from_le_bytes(*oracle_band_bps),
+            admin_pubkey: Pubkey::new_from_array(*admin_pubkey),
+            paused_flags: paused_flags[0],
+            trade_fee_bps: u64::from_le_bytes(*trade_fee_bps),
+            keeper_reward: u64::from_le_bytes(*keeper_reward),
+            keeper_reward_interval_slots: u64::from_le_bytes(*keeper_reward_interval_slots),
+            last_keeper_reward_slot: u64::from_le_bytes(*last_keeper_reward_slot),
+            min_correction_interval_slots: u64::from_le_bytes(*min_correction_interval_slots),
+            correction_override_bps: u64::from_le_bytes(*correction_override_bps),
+            max_correction_amount: u64::from_le_bytes(*max_correction_amount),
+            max_correction_bps: u64::from_le_bytes(*max_correction_bps),
+            bump_seed: bump_seed[0],
+            pyth_program_id: Pubkey::new_from_array(*pyth_program_id),
+            pyth_product_pubkey: Pubkey::new_from_array(*pyth_product_pubkey),
+            pyth_price_pubkey: Pubkey::new_from_array(*pyth_price_pubkey),
         })
     }
 
@@ -57,7 +121,22 @@ impl Pack for Oracle {
             amm_program_id_dst,
             lpcp_program_id_dst,
             ucp_program_id_dst,
-        ) = mut_array_refs![dst, 1, 32, 32, 32, 32];
+            oracle_band_bps_dst,
+            admin_pubkey_dst,
+            paused_flags_dst,
+            trade_fee_bps_dst,
+            keeper_reward_dst,
+            keeper_reward_interval_slots_dst,
+            last_keeper_reward_slot_dst,
+            min_correction_interval_slots_dst,
+            correction_override_bps_dst,
+            max_correction_amount_dst,
+            max_correction_bps_dst,
+            bump_seed_dst,
+            pyth_program_id_dst,
+            pyth_product_pubkey_dst,
+            pyth_price_pubkey_dst,
+        ) = mut_array_refs![dst, 1, 32, 32, 32, 32, 8, 32, 1, 8, 8, 8, 8, 8, 8, 8, 8, 1, 32, 32, 32];
 
         let Oracle {
             is_initialized,
@@ -65,6 +144,21 @@ impl Pack for Oracle {
             amm_program_id,
             lpcp_program_id,
             ucp_program_id,
+            oracle_band_bps,
+            admin_pubkey,
+            paused_flags,
+            trade_fee_bps,
+            keeper_reward,
+            keeper_reward_interval_slots,
+            last_keeper_reward_slot,
+            min_correction_interval_slots,
+            correction_override_bps,
+            max_correction_amount,
+            max_correction_bps,
+            bump_seed,
+            pyth_program_id,
+            pyth_product_pubkey,
+            pyth_price_pubkey,
         } = self;
 
         is_initialized_dst[0] = *is_initialized as u8;
@@ -72,6 +166,21 @@ impl Pack for Oracle {
         amm_program_id_dst.copy_from_slice(amm_program_id.as_ref());
         lpcp_program_id_dst.copy_from_slice(lpcp_program_id.as_ref());
         ucp_program_id_dst.copy_from_slice(ucp_program_id.as_ref());
+        *oracle_band_bps_dst = oracle_band_bps.to_le_bytes();
+        admin_pubkey_dst.copy_from_slice(admin_pubkey.as_ref());
+        paused_flags_dst[0] = *paused_flags;
+        *trade_fee_bps_dst = trade_fee_bps.to_le_bytes();
+        *keeper_reward_dst = keeper_reward.to_le_bytes();
+        *keeper_reward_interval_slots_dst = keeper_reward_interval_slots.to_le_bytes();
+        *last_keeper_reward_slot_dst = last_keeper_reward_slot.to_le_bytes();
+        *min_correction_interval_slots_dst = min_correction_interval_slots.to_le_bytes();
+        *correction_override_bps_dst = correction_override_bps.to_le_bytes();
+        *max_correction_amount_dst = max_correction_amount.to_le_bytes();
+        *max_correction_bps_dst = max_correction_bps.to_le_bytes();
+        bump_seed_dst[0] = *bump_seed;
+        pyth_program_id_dst.copy_from_slice(pyth_program_id.as_ref());
+        pyth_product_pubkey_dst.copy_from_slice(pyth_product_pubkey.as_ref());
+        pyth_price_pubkey_dst.copy_from_slice(pyth_price_pubkey.as_ref());
     }
 }
 
@@ -154,4 +263,194 @@ impl Pack for AMM {
         ucp_pda_token_account_pubkey_dst.copy_from_slice(ucp_pda_token_account_pubkey.as_ref());
         *last_trade_spread_dst = last_trade_spread.to_le_bytes();
     }
-}
\ No newline at end of file
+}
+
+pub struct CurveConfig {
+    pub is_initialized: bool,
+    pub oracle_pubkey: Pubkey,
+    pub amm_pubkey: Pubkey,
+    pub curve_type: u8,
+    pub amplification: u64,
+    /// Slot and Pyth price of the last correction that minted or burned the AMM's iAsset
+    pub last_correction_slot: u64,
+    pub last_correction_price: u64,
+}
+
+impl CurveConfig {
+    /// The curve the AMM is priced with, see `curve::CurveType`
+    pub fn curve(&self) -> Result<Box<dyn Curve>, ProgramError> {
+        curve_from_config(self.curve_type, self.amplification)
+    }
+}
+
+impl Sealed for CurveConfig {}
+
+impl IsInitialized for CurveConfig {
+    fn is_initialized(&self) -> bool {
+        self.is_initialized
+    }
+}
+
+impl Pack for CurveConfig {
+    const LEN: usize = 90;
+    fn unpack_from_slice(src: &[u8]) -> Result<Self, ProgramError> {
+        let src = array_ref![src, 0, CurveConfig::LEN];
+        let (
+            is_initialized,
+            oracle_pubkey,
+            amm_pubkey,
+            curve_type,
+            amplification,
+            last_correction_slot,
+            last_correction_price,
+        ) = array_refs![src, 1, 32, 32, 1, 8, 8, 8];
+        let is_initialized = match is_initialized {
+            [0] => false,
+            [1] => true,
+            _ => return Err(ProgramError::InvalidAccountData),
+        };
+
+        Ok(CurveConfig {
+            is_initialized,
+            oracle_pubkey: Pubkey::new_from_array(*oracle_pubkey),
+            amm_pubkey: Pubkey::new_from_array(*amm_pubkey),
+            curve_type: curve_type[0],
+            amplification: u64::from_le_bytes(*amplification),
+            last_correction_slot: u64::from_le_bytes(*last_correction_slot),
+            last_correction_price: u64::from_le_bytes(*last_correction_price),
+        })
+    }
+
+    fn pack_into_slice(&self, dst: &mut [u8]) {
+        let dst = array_mut_ref![dst, 0, CurveConfig::LEN];
+        let (
+            is_initialized_dst,
+            oracle_pubkey_dst,
+            amm_pubkey_dst,
+            curve_type_dst,
+            amplification_dst,
+            last_correction_slot_dst,
+            last_correction_price_dst,
+        ) = mut_array_refs![dst, 1, 32, 32, 1, 8, 8, 8];
+
+        let CurveConfig {
+            is_initialized,
+            oracle_pubkey,
+            amm_pubkey,
+            curve_type,
+            amplification,
+            last_correction_slot,
+            last_correction_price,
+        } = self;
+
+        is_initialized_dst[0] = *is_initialized as u8;
+        oracle_pubkey_dst.copy_from_slice(oracle_pubkey.as_ref());
+        amm_pubkey_dst.copy_from_slice(amm_pubkey.as_ref());
+        curve_type_dst[0] = *curve_type;
+        *amplification_dst = amplification.to_le_bytes();
+        *last_correction_slot_dst = last_correction_slot.to_le_bytes();
+        *last_correction_price_dst = last_correction_price.to_le_bytes();
+    }
+}
+
+pub struct TradeQuote {
+    pub amount_out: u64,
+    pub spread: u64,
+    pub fair_value: u64,
+    pub trade_fee: u64,
+}
+
+impl Sealed for TradeQuote {}
+
+impl Pack for TradeQuote {
+    const LEN: usize = 32;
+    fn unpack_from_slice(src: &[u8]) -> Result<Self, ProgramError> {
+        let src = array_ref![src, 0, TradeQuote::LEN];
+        let (
+            amount_out,
+            spread,
+            fair_value,
+            trade_fee,
+        ) = array_refs![src, 8, 8, 8, 8];
+
+        Ok(TradeQuote {
+            amount_out: u64::from_le_bytes(*amount_out),
+            spread: u64::from_le_bytes(*spread),
+            fair_value: u64::from_le_bytes(*fair_value),
+            trade_fee: u64::from_le_bytes(*trade_fee),
+        })
+    }
+
+    fn pack_into_slice(&self, dst: &mut [u8]) {
+        let dst = array_mut_ref![dst, 0, TradeQuote::LEN];
+        let (
+            amount_out_dst,
+            spread_dst,
+            fair_value_dst,
+            trade_fee_dst,
+        ) = mut_array_refs![dst, 8, 8, 8, 8];
+
+        let TradeQuote {
+            amount_out,
+            spread,
+            fair_value,
+            trade_fee,
+        } = self;
+
+        *amount_out_dst = amount_out.to_le_bytes();
+        *spread_dst = spread.to_le_bytes();
+        *fair_value_dst = fair_value.to_le_bytes();
+        *trade_fee_dst = trade_fee.to_le_bytes();
+    }
+}
+
+
+pub struct OraclePrice {
+    pub price: u64,
+    pub conf: u64,
+    pub publish_slot: u64,
+}
+
+impl OraclePrice {
+    /// Number of decimals in `price` and `conf`
+    pub const DECIMALS: u32 = 9;
+}
+
+impl Sealed for OraclePrice {}
+
+impl Pack for OraclePrice {
+    const LEN: usize = 24;
+    fn unpack_from_slice(src: &[u8]) -> Result<Self, ProgramError> {
+        let src = array_ref![src, 0, OraclePrice::LEN];
+        let (
+            price,
+            conf,
+            publish_slot,
+        ) = array_refs![src, 8, 8, 8];
+
+        Ok(OraclePrice {
+            price: u64::from_le_bytes(*price),
+            conf: u64::from_le_bytes(*conf),
+            publish_slot: u64::from_le_bytes(*publish_slot),
+        })
+    }
+
+    fn pack_into_slice(&self, dst: &mut [u8]) {
+        let dst = array_mut_ref![dst, 0, OraclePrice::LEN];
+        let (
+            price_dst,
+            conf_dst,
+            publish_slot_dst,
+        ) = mut_array_refs![dst, 8, 8, 8];
+
+        let OraclePrice {
+            price,
+            conf,
+            publish_slot,
+        } = self;
+
+        *price_dst = price.to_le_bytes();
+        *conf_dst = conf.to_le_bytes();
+        *publish_slot_dst = publish_slot.to_le_bytes();
+    }
+}
@@ -0,0 +1,411 @@
+mod common;
+
+use common::*;
+use oracle_program::{curve::ConstantProduct, error::OracleError, math::Rounding, processor::calc_lpr_usdi, states::{CurveConfig, Oracle}};
+use solana_program::{program_pack::Pack, pubkey::Pubkey};
+use solana_program_test::{BanksClient, ProgramTest};
+use solana_sdk::{
+    account::{Account, AccountSharedData},
+    instruction::InstructionError,
+    signature::{Keypair, Signer},
+    transaction::TransactionError,
+};
+use spl_token::state::Mint;
+
+/// Fees collected before each test, in USDI base units
+const FEE_BALANCE: u64 = 1_000_000;
+
+/// Pyth price of 10 USDI per iAsset, the price of every `pool`
+const PYTH_PRICE: i64 = 1_000_000_000;
+
+/// An AMM holding one million USDI and 100k iAsset with {iasset_decimals}, 1k more
+/// iAsset held by users
+fn pool(iasset_decimals: u8) -> Pool {
+    Pool::new(1_000_000, 100_000, iasset_decimals)
+}
+
+/// A market around {pools} priced at {pyth_price}, with {FEE_BALANCE} of fees collected
+/// and {configure} applied to the Oracle
+fn correction_market(amm: StandInAmm, pyth_price: i64, pools: &[Pool], configure: impl FnOnce(&mut Oracle)) -> (Market, ProgramTest) {
+    let (market, mut program_test) = Market::with_amm(amm, 0, pools, configure);
+    market.set_pyth_price(&mut program_test, pyth_price, 0, 0);
+    market.set_fee_balance(&mut program_test, FEE_BALANCE);
+    (market, program_test)
+}
+
+/// Corrects the AMMs at {amm_indices} with a fresh keeper, so repeated runs are distinct transactions
+async fn run_collateral_correction(
+    banks_client: &mut BanksClient,
+    payer: &Keypair,
+    market: &Market,
+    amm_indices: &[usize],
+) -> Result<(), TransactionError> {
+    let keeper = Keypair::new();
+    process(banks_client, payer, market.collateral_correction(amm_indices, &keeper.pubkey()), &[&keeper]).await
+}
+
+/// UCP and LPCP collateral balances
+async fn pool_collateral(banks_client: &mut BanksClient, market: &Market) -> (u64, u64) {
+    (
+        token_amount(banks_client, market.ucp_collateral).await,
+        token_amount(banks_client, market.lpcp_collateral).await,
+    )
+}
+
+async fn curve_config(banks_client: &mut BanksClient, market: &Market, amm_index: usize) -> CurveConfig {
+    CurveConfig::unpack(&banks_client.get_account(market.amms[amm_index].curve_config).await.unwrap().unwrap().data).unwrap()
+}
+
+/// The LPR, in USDI base units, of the current balances of the AMM at {amm_index}
+/// with {user_owned_iasset} sold back in
+async fn lpr_usdi(banks_client: &mut BanksClient, market: &Market, amm_index: usize, user_owned_iasset: u64, rounding: Rounding) -> u64 {
+    let amm = &market.amms[amm_index];
+    let usdi_amm_amount = token_amount(banks_client, amm.usdi_token_account).await;
+    let iasset_amm_amount = token_amount(banks_client, amm.iasset_token_account).await;
+    let iasset_mint = banks_client.get_account(amm.iasset_mint).await.unwrap().unwrap();
+    let iasset_decimals = Mint::unpack(&iasset_mint.data).unwrap().decimals;
+    calc_lpr_usdi(
+        &ConstantProduct,
+        usdi_amm_amount,
+        iasset_amm_amount,
+        user_owned_iasset,
+        USDI_DECIMALS.into(),
+        iasset_decimals.into(),
+        rounding,
+    )
+    .unwrap()
+}
+
+#[tokio::test]
+async fn correction_skips_collateral_transfer_that_rounds_to_zero() {
+    let (market, program_test) = correction_market(StandInAmm::Noop, PYTH_PRICE, &[pool(6)], |_| ());
+    let (mut banks_client, payer, _) = program_test.start().await;
+
+    run_collateral_correction(&mut banks_client, &payer, &market, &[0]).await.unwrap();
+
+    assert_eq!(pool_collateral(&mut banks_client, &market).await, (POOL_COLLATERAL, POOL_COLLATERAL));
+}
+
+#[tokio::test]
+async fn correction_mint_sends_collateral_from_lpcp_to_ucp() {
+    let (market, program_test) = correction_market(StandInAmm::Trading, PYTH_PRICE, &[pool(6)], |_| ());
+    let (mut banks_client, payer, _) = program_test.start().await;
+
+    let user_owned_iasset = units(1_000, 6);
+    let lpr_before = lpr_usdi(&mut banks_client, &market, 0, user_owned_iasset, Rounding::RoundDown).await;
+    run_collateral_correction(&mut banks_client, &payer, &market, &[0]).await.unwrap();
+
+    assert!(token_amount(&mut banks_client, market.amms[0].iasset_token_account).await > units(100_000, 6));
+
+    let moved = lpr_usdi(&mut banks_client, &market, 0, user_owned_iasset, Rounding::RoundUp).await - lpr_before;
+    assert!(moved > 0);
+    assert_eq!(
+        pool_collateral(&mut banks_client, &market).await,
+        (POOL_COLLATERAL + moved, POOL_COLLATERAL - moved)
+    );
+}
+
+#[tokio::test]
+async fn correction_burn_sends_collateral_from_ucp_to_lpcp() {
+    let (market, program_test) = correction_market(StandInAmm::Trading, 1_000 * PYTH_PRICE, &[pool(6)], |_| ());
+    let (mut banks_client, payer, _) = program_test.start().await;
+
+    let user_owned_iasset = units(1_000, 6);
+    let lpr_before = lpr_usdi(&mut banks_client, &market, 0, user_owned_iasset, Rounding::RoundDown).await;
+    run_collateral_correction(&mut banks_client, &payer, &market, &[0]).await.unwrap();
+
+    assert!(token_amount(&mut banks_client, market.amms[0].iasset_token_account).await < units(100_000, 6));
+
+    let moved = lpr_before - lpr_usdi(&mut banks_client, &market, 0, user_owned_iasset, Rounding::RoundUp).await;
+    assert!(moved > 0);
+    assert_eq!(
+        pool_collateral(&mut banks_client, &market).await,
+        (POOL_COLLATERAL - moved, POOL_COLLATERAL + moved)
+    );
+}
+
+#[tokio::test]
+async fn correction_of_two_amms_settles_their_net_collateral_once() {
+    let pools = [pool(6), Pool::new(2_000_000, 200_000, 9)];
+    let (market, program_test) = correction_market(StandInAmm::Trading, PYTH_PRICE, &pools, |_| ());
+    let (mut banks_client, payer, _) = program_test.start().await;
+
+    let user_owned_iasset = [units(1_000, 6), units(1_000, 9)];
+    let mut lprs_before = Vec::new();
+    for (amm_index, &user_owned_iasset) in user_owned_iasset.iter().enumerate() {
+        lprs_before.push(lpr_usdi(&mut banks_client, &market, amm_index, user_owned_iasset, Rounding::RoundDown).await);
+    }
+    run_collateral_correction(&mut banks_client, &payer, &market, &[0, 1]).await.unwrap();
+
+    let mut moved = 0;
+    for (amm_index, pool) in pools.iter().enumerate() {
+        assert!(token_amount(&mut banks_client, market.amms[amm_index].iasset_token_account).await > pool.iasset_amount);
+        let lpr_after = lpr_usdi(&mut banks_client, &market, amm_index, user_owned_iasset[amm_index], Rounding::RoundUp).await;
+        moved += lpr_after - lprs_before[amm_index];
+    }
+    assert_eq!(
+        pool_collateral(&mut banks_client, &market).await,
+        (POOL_COLLATERAL + moved, POOL_COLLATERAL - moved)
+    );
+}
+
+#[tokio::test]
+async fn correction_rejects_mint_supply_below_amm_balance() {
+    let short_supply = Pool { iasset_supply: units(90_000, 6), ..pool(6) };
+    let (market, program_test) = correction_market(StandInAmm::Trading, PYTH_PRICE, &[short_supply], |_| ());
+    let (mut banks_client, payer, _) = program_test.start().await;
+
+    let err = run_collateral_correction(&mut banks_client, &payer, &market, &[0]).await.unwrap_err();
+    assert_eq!(err, oracle_error(OracleError::MathUnderflow));
+}
+
+async fn minted_iasset(iasset_decimals: u8) -> u64 {
+    let (market, program_test) = correction_market(StandInAmm::Trading, PYTH_PRICE, &[pool(iasset_decimals)], |_| ());
+    let (mut banks_client, payer, _) = program_test.start().await;
+
+    run_collateral_correction(&mut banks_client, &payer, &market, &[0]).await.unwrap();
+
+    token_amount(&mut banks_client, market.amms[0].iasset_token_account).await - units(100_000, iasset_decimals)
+}
+
+#[tokio::test]
+async fn correction_scales_by_iasset_decimals() {
+    let minted_with_6_decimals = minted_iasset(6).await;
+    let minted_with_9_decimals = minted_iasset(9).await;
+
+    assert!(minted_with_6_decimals > 0);
+    assert_eq!(minted_with_9_decimals / 1_000, minted_with_6_decimals);
+}
+
+#[tokio::test]
+async fn set_curve_selects_the_curve_used_by_the_correction() {
+    let minted_with_constant_product = minted_iasset(6).await;
+
+    let (market, program_test) = correction_market(StandInAmm::Trading, PYTH_PRICE, &[pool(6)], |_| ());
+    let (mut banks_client, payer, _) = program_test.start().await;
+    process(&mut banks_client, &payer, market.set_curve(0, 1, 100), &[&market.admin]).await.unwrap();
+
+    let curve_config = curve_config(&mut banks_client, &market, 0).await;
+    assert_eq!((curve_config.curve_type, curve_config.amplification), (1, 100));
+
+    run_collateral_correction(&mut banks_client, &payer, &market, &[0]).await.unwrap();
+    let minted = token_amount(&mut banks_client, market.amms[0].iasset_token_account).await - units(100_000, 6);
+    assert_ne!(minted, minted_with_constant_product);
+}
+
+#[tokio::test]
+async fn set_curve_rejects_an_unknown_curve() {
+    let (market, program_test) = correction_market(StandInAmm::Noop, PYTH_PRICE, &[pool(6)], |_| ());
+    let (mut banks_client, payer, _) = program_test.start().await;
+
+    let err = process(&mut banks_client, &payer, market.set_curve(0, 2, 100), &[&market.admin]).await.unwrap_err();
+    assert_eq!(err, oracle_error(OracleError::InvalidCurve));
+}
+
+#[tokio::test]
+async fn correction_rejects_a_curve_config_for_another_amm() {
+    let (mut market, mut program_test) = correction_market(StandInAmm::Noop, PYTH_PRICE, &[pool(6)], |_| ());
+    let other_curve_config = CurveConfig {
+        is_initialized: true,
+        oracle_pubkey: market.oracle,
+        amm_pubkey: Pubkey::new_unique(),
+        curve_type: 1,
+        amplification: 100,
+        last_correction_slot: 0,
+        last_correction_price: 0,
+    };
+    market.amms[0].curve_config = Pubkey::new_unique();
+    add_account(&mut program_test, market.amms[0].curve_config, pack(other_curve_config), market.program_id);
+    let (mut banks_client, payer, _) = program_test.start().await;
+
+    let err = run_collateral_correction(&mut banks_client, &payer, &market, &[0]).await.unwrap_err();
+    assert_eq!(err, oracle_error(OracleError::InvalidCurve));
+}
+
+#[tokio::test]
+async fn correction_rejects_a_pyth_feed_other_than_the_oracles() {
+    let (market, mut program_test) = correction_market(StandInAmm::Trading, PYTH_PRICE, &[pool(6)], |_| ());
+    let (pyth_product, pyth_price) = (Pubkey::new_unique(), Pubkey::new_unique());
+    add_account(&mut program_test, pyth_product, pyth_product_data(&pyth_price), market.pyth_program_id);
+    add_account(&mut program_test, pyth_price, pyth_price_data(1_000 * PYTH_PRICE), market.pyth_program_id);
+    let market = Market { pyth_product, pyth_price, ..market };
+    let (mut banks_client, payer, _) = program_test.start().await;
+
+    let err = run_collateral_correction(&mut banks_client, &payer, &market, &[0]).await.unwrap_err();
+    assert_eq!(err, oracle_error(OracleError::InvalidOraclePrice));
+}
+
+#[tokio::test]
+async fn correction_rejects_pyth_accounts_not_owned_by_the_pyth_program() {
+    let (market, program_test) = correction_market(StandInAmm::Trading, PYTH_PRICE, &[pool(6)], |oracle| {
+        oracle.pyth_program_id = Pubkey::new_unique();
+    });
+    let (mut banks_client, payer, _) = program_test.start().await;
+
+    let err = run_collateral_correction(&mut banks_client, &payer, &market, &[0]).await.unwrap_err();
+    assert_eq!(err, TransactionError::InstructionError(0, InstructionError::IncorrectProgramId));
+}
+
+#[tokio::test]
+async fn second_correction_inside_the_interval_is_rejected() {
+    let (market, program_test) = correction_market(StandInAmm::Trading, PYTH_PRICE, &[pool(6)], |oracle| {
+        oracle.min_correction_interval_slots = 1_000_000;
+    });
+    let (mut banks_client, payer, _) = program_test.start().await;
+
+    run_collateral_correction(&mut banks_client, &payer, &market, &[0]).await.unwrap();
+    let err = run_collateral_correction(&mut banks_client, &payer, &market, &[0]).await.unwrap_err();
+    assert_eq!(err, oracle_error(OracleError::CorrectionTooFrequent));
+}
+
+#[tokio::test]
+async fn correction_of_no_amms_is_rejected() {
+    let (market, program_test) = correction_market(StandInAmm::Trading, PYTH_PRICE, &[pool(6)], |_| ());
+    let (mut banks_client, payer, _) = program_test.start().await;
+
+    let err = run_collateral_correction(&mut banks_client, &payer, &market, &[]).await.unwrap_err();
+    assert_eq!(err, TransactionError::InstructionError(0, InstructionError::InvalidInstructionData));
+}
+
+#[tokio::test]
+async fn correction_rejects_the_same_amm_twice() {
+    let (market, program_test) = correction_market(StandInAmm::Trading, PYTH_PRICE, &[pool(6)], |oracle| {
+        oracle.max_correction_amount = units(10, 6);
+    });
+    let (mut banks_client, payer, _) = program_test.start().await;
+
+    let err = run_collateral_correction(&mut banks_client, &payer, &market, &[0, 0]).await.unwrap_err();
+    assert_eq!(err, oracle_error(OracleError::DuplicateAmm));
+    assert_eq!(token_amount(&mut banks_client, market.amms[0].iasset_token_account).await, units(100_000, 6));
+}
+
+#[tokio::test]
+async fn correction_that_changes_nothing_does_not_start_the_interval() {
+    let (market, program_test) = correction_market(StandInAmm::Noop, PYTH_PRICE, &[pool(6)], |oracle| {
+        oracle.min_correction_interval_slots = 1_000_000;
+    });
+    let (mut banks_client, payer, _) = program_test.start().await;
+
+    run_collateral_correction(&mut banks_client, &payer, &market, &[0]).await.unwrap();
+    let curve_config = curve_config(&mut banks_client, &market, 0).await;
+    assert_eq!((curve_config.last_correction_slot, curve_config.last_correction_price), (0, 0));
+
+    run_collateral_correction(&mut banks_client, &payer, &market, &[0]).await.unwrap();
+}
+
+#[tokio::test]
+async fn correcting_one_amm_does_not_hold_back_another() {
+    let (market, program_test) = correction_market(StandInAmm::Trading, PYTH_PRICE, &[pool(6), pool(6)], |oracle| {
+        oracle.min_correction_interval_slots = 1_000_000;
+    });
+    let (mut banks_client, payer, _) = program_test.start().await;
+
+    run_collateral_correction(&mut banks_client, &payer, &market, &[0]).await.unwrap();
+    assert_eq!(curve_config(&mut banks_client, &market, 0).await.last_correction_price, 10_000_000_000);
+    assert_eq!(curve_config(&mut banks_client, &market, 1).await.last_correction_price, 0);
+
+    run_collateral_correction(&mut banks_client, &payer, &market, &[1]).await.unwrap();
+    let err = run_collateral_correction(&mut banks_client, &payer, &market, &[0]).await.unwrap_err();
+    assert_eq!(err, oracle_error(OracleError::CorrectionTooFrequent));
+}
+
+/// Runs two corrections inside the interval, with the Pyth price moved 10% between them
+async fn correct_after_price_move(correction_override_bps: u64) -> Result<(), TransactionError> {
+    let (market, program_test) = correction_market(StandInAmm::Trading, PYTH_PRICE, &[pool(6)], |oracle| {
+        oracle.min_correction_interval_slots = 1_000_000;
+        oracle.correction_override_bps = correction_override_bps;
+    });
+    let mut context = program_test.start_with_context().await;
+    run_collateral_correction(&mut context.banks_client, &context.payer, &market, &[0]).await.unwrap();
+
+    let moved_price = Account {
+        lamports: 1_000_000_000,
+        data: pyth_price_data(PYTH_PRICE + PYTH_PRICE / 10),
+        owner: market.pyth_program_id,
+        executable: false,
+        rent_epoch: 0,
+    };
+    context.set_account(&market.pyth_price, &AccountSharedData::from(moved_price));
+    run_collateral_correction(&mut context.banks_client, &context.payer, &market, &[0]).await
+}
+
+#[tokio::test]
+async fn correction_override_skips_the_interval_after_a_large_price_move() {
+    correct_after_price_move(500).await.unwrap();
+}
+
+#[tokio::test]
+async fn correction_override_of_zero_never_skips_the_interval() {
+    let err = correct_after_price_move(0).await.unwrap_err();
+    assert_eq!(err, oracle_error(OracleError::CorrectionTooFrequent));
+}
+
+/// Runs one correction with a keeper reward of 100, returning the reward paid
+async fn keeper_reward_paid(amm: StandInAmm, keeper_reward_interval_slots: u64) -> u64 {
+    let (market, program_test) = correction_market(amm, PYTH_PRICE, &[pool(6)], |oracle| {
+        oracle.keeper_reward = 100;
+        oracle.keeper_reward_interval_slots = keeper_reward_interval_slots;
+    });
+    let (mut banks_client, payer, _) = program_test.start().await;
+
+    run_collateral_correction(&mut banks_client, &payer, &market, &[0]).await.unwrap();
+
+    let reward = token_amount(&mut banks_client, market.keeper_reward_token_account).await;
+    assert_eq!(token_amount(&mut banks_client, market.fee_token_account).await, FEE_BALANCE - reward);
+    reward
+}
+
+#[tokio::test]
+async fn keeper_is_paid_for_a_correction_that_changes_state() {
+    assert_eq!(keeper_reward_paid(StandInAmm::Trading, 0).await, 100);
+}
+
+#[tokio::test]
+async fn keeper_is_not_paid_inside_the_reward_interval() {
+    assert_eq!(keeper_reward_paid(StandInAmm::Trading, 1_000_000).await, 0);
+}
+
+#[tokio::test]
+async fn keeper_is_not_paid_for_a_correction_that_changes_nothing() {
+    assert_eq!(keeper_reward_paid(StandInAmm::Noop, 0).await, 0);
+}
+
+#[tokio::test]
+async fn pausing_correction_blocks_the_correction() {
+    let (market, program_test) = correction_market(StandInAmm::Trading, PYTH_PRICE, &[pool(6)], |oracle| {
+        oracle.paused_flags = Oracle::PAUSE_CORRECTION;
+    });
+    let (mut banks_client, payer, _) = program_test.start().await;
+
+    let err = run_collateral_correction(&mut banks_client, &payer, &market, &[0]).await.unwrap_err();
+    assert_eq!(err, oracle_error(OracleError::Paused));
+    assert_eq!(pool_collateral(&mut banks_client, &market).await, (POOL_COLLATERAL, POOL_COLLATERAL));
+}
+
+/// iAsset minted by a correction of the mint case, with {configure} setting its caps
+async fn capped_mint(configure: impl FnOnce(&mut Oracle)) -> u64 {
+    let (market, program_test) = correction_market(StandInAmm::Trading, PYTH_PRICE, &[pool(6)], configure);
+    let (mut banks_client, payer, _) = program_test.start().await;
+
+    run_collateral_correction(&mut banks_client, &payer, &market, &[0]).await.unwrap();
+    token_amount(&mut banks_client, market.amms[0].iasset_token_account).await - units(100_000, 6)
+}
+
+#[tokio::test]
+async fn correction_is_clamped_to_max_correction_amount() {
+    assert!(capped_mint(|_| ()).await > units(10, 6));
+    assert_eq!(capped_mint(|oracle| oracle.max_correction_amount = units(10, 6)).await, units(10, 6));
+}
+
+#[tokio::test]
+async fn correction_is_clamped_to_max_correction_bps() {
+    //10 bps of the AMM's 100k iAsset
+    assert_eq!(capped_mint(|oracle| oracle.max_correction_bps = 10).await, units(100, 6));
+
+    //The tighter of the two caps applies
+    let both_caps = |oracle: &mut Oracle| {
+        oracle.max_correction_amount = units(10, 6);
+        oracle.max_correction_bps = 10;
+    };
+    assert_eq!(capped_mint(both_caps).await, units(10, 6));
+}
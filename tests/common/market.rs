@@ -0,0 +1,509 @@
+//! A full trading setup around the stand-in programs: an initialized Oracle, its
+//! Pyth feed, the UCP and LPCP, a user and one constant-product AMM per `Pool`
+
+use super::*;
+use oracle_program::{
+    cpi,
+    instruction::OracleInstruction,
+    processor::Processor,
+    states::{CurveConfig, Oracle, AMM},
+};
+use solana_program::{
+    instruction::{AccountMeta, Instruction},
+    sysvar,
+};
+use solana_program_test::{processor, BanksClient, ProgramTest};
+use solana_sdk::{
+    signature::{Keypair, Signer},
+    transaction::{Transaction, TransactionError},
+};
+
+/// Oracle price of each iAsset in the market, 100 USDI
+pub const MARKET_PYTH_PRICE: i64 = 10_000_000_000;
+
+/// Oracle band of the market, in basis points
+pub const MARKET_ORACLE_BAND_BPS: u64 = 500;
+
+/// Balances of one AMM and of the user's token account for its iAsset
+pub struct Pool {
+    pub usdi_amount: u64,
+    pub iasset_amount: u64,
+    pub iasset_decimals: u8,
+    pub user_iasset_amount: u64,
+    pub iasset_supply: u64,
+}
+
+impl Pool {
+    /// An AMM holding {usdi} whole USDI and {iasset} whole iAsset with {iasset_decimals},
+    /// with the user holding a thousand of its iAsset
+    pub fn new(usdi: u64, iasset: u64, iasset_decimals: u8) -> Self {
+        Pool {
+            usdi_amount: units(usdi, USDI_DECIMALS),
+            iasset_amount: units(iasset, iasset_decimals),
+            iasset_decimals,
+            user_iasset_amount: units(1_000, iasset_decimals),
+            iasset_supply: units(iasset + 1_000, iasset_decimals),
+        }
+    }
+
+    /// An AMM priced at {MARKET_PYTH_PRICE}, holding one million USDI
+    pub fn at_oracle_price() -> Self {
+        Self::new(1_000_000, 10_000, USDI_DECIMALS)
+    }
+}
+
+pub struct MarketAmm {
+    pub amm_account: Pubkey,
+    pub usdi_token_account: Pubkey,
+    pub iasset_token_account: Pubkey,
+    pub iasset_mint: Pubkey,
+    pub curve_config: Pubkey,
+    pub user_iasset_token_account: Pubkey,
+}
+
+pub struct Market {
+    pub program_id: Pubkey,
+    pub amm_program_id: Pubkey,
+    pub ucp_program_id: Pubkey,
+    pub lpcp_program_id: Pubkey,
+    pub pyth_program_id: Pubkey,
+    pub pyth_product: Pubkey,
+    pub pyth_price: Pubkey,
+    pub admin: Keypair,
+    pub user: Keypair,
+    pub oracle: Pubkey,
+    pub oracle_pda: Pubkey,
+    pub amm_pda: Pubkey,
+    pub fee_token_account: Pubkey,
+    pub usdi_mint: Pubkey,
+    pub ucp_account: Pubkey,
+    pub ucp_pda: Pubkey,
+    pub lpcp_account: Pubkey,
+    pub lpcp_pda: Pubkey,
+    pub ucp_collateral: Pubkey,
+    pub lpcp_collateral: Pubkey,
+    pub keeper_reward_token_account: Pubkey,
+    pub user_usdi_token_account: Pubkey,
+    pub amms: Vec<MarketAmm>,
+}
+
+impl Market {
+    /// A market holding {pools}, with the user holding {user_usdi_amount} USDI and
+    /// {configure} applied to the Oracle account before it is stored
+    pub fn new(user_usdi_amount: u64, pools: &[Pool], configure: impl FnOnce(&mut Oracle)) -> (Self, ProgramTest) {
+        Self::with_amm(StandInAmm::Trading, user_usdi_amount, pools, configure)
+    }
+
+    /// Like `new`, with {amm} as the AMM program
+    pub fn with_amm(amm: StandInAmm, user_usdi_amount: u64, pools: &[Pool], configure: impl FnOnce(&mut Oracle)) -> (Self, ProgramTest) {
+        let program_id = Pubkey::new_unique();
+        let amm_program_id = Pubkey::new_unique();
+        let ucp_program_id = Pubkey::new_unique();
+        let lpcp_program_id = Pubkey::new_unique();
+        let pyth_program_id = Pubkey::new_unique();
+
+        let mut program_test = ProgramTest::new("oracle_program", program_id, processor!(Processor::process));
+        program_test.prefer_bpf(false);
+        match amm {
+            StandInAmm::Trading => program_test.add_program("stand_in_amm", amm_program_id, processor!(process_stand_in_amm)),
+            StandInAmm::Noop => program_test.add_program("stand_in_amm", amm_program_id, processor!(process_noop_amm)),
+        }
+        program_test.add_program("stand_in_ucp", ucp_program_id, processor!(process_collateral_pool));
+        program_test.add_program("stand_in_lpcp", lpcp_program_id, processor!(process_collateral_pool));
+
+        let oracle = Pubkey::new_unique();
+        let (oracle_pda, bump_seed) = cpi::find_oracle_pda(&oracle, &program_id);
+        let admin = Keypair::new();
+        let user = Keypair::new();
+        let usdi_mint = Pubkey::new_unique();
+
+        let amms: Vec<MarketAmm> = pools
+            .iter()
+            .map(|_| MarketAmm {
+                amm_account: Pubkey::new_unique(),
+                usdi_token_account: Pubkey::new_unique(),
+                iasset_token_account: Pubkey::new_unique(),
+                iasset_mint: Pubkey::new_unique(),
+                curve_config: Pubkey::new_unique(),
+                user_iasset_token_account: Pubkey::new_unique(),
+            })
+            .collect();
+
+        let market = Market {
+            program_id,
+            amm_program_id,
+            ucp_program_id,
+            lpcp_program_id,
+            pyth_program_id,
+            pyth_product: Pubkey::new_unique(),
+            pyth_price: Pubkey::new_unique(),
+            admin,
+            user,
+            oracle,
+            oracle_pda,
+            amm_pda: find_amm_pda(&amm_program_id),
+            fee_token_account: Pubkey::new_unique(),
+            usdi_mint,
+            ucp_account: Pubkey::new_unique(),
+            ucp_pda: find_pool_pda(&ucp_program_id),
+            lpcp_account: Pubkey::new_unique(),
+            lpcp_pda: find_pool_pda(&lpcp_program_id),
+            ucp_collateral: Pubkey::new_unique(),
+            lpcp_collateral: Pubkey::new_unique(),
+            keeper_reward_token_account: Pubkey::new_unique(),
+            user_usdi_token_account: Pubkey::new_unique(),
+            amms,
+        };
+
+        add_account(&mut program_test, market.pyth_product, pyth_product_data(&market.pyth_price), pyth_program_id);
+        add_account(&mut program_test, market.pyth_price, pyth_price_data(MARKET_PYTH_PRICE), pyth_program_id);
+
+        let mut oracle_info = new_oracle(bump_seed);
+        oracle_info.fee_token_account_pubkey = market.fee_token_account;
+        oracle_info.amm_program_id = amm_program_id;
+        oracle_info.ucp_program_id = ucp_program_id;
+        oracle_info.lpcp_program_id = lpcp_program_id;
+        oracle_info.admin_pubkey = market.admin.pubkey();
+        oracle_info.oracle_band_bps = MARKET_ORACLE_BAND_BPS;
+        oracle_info.pyth_program_id = pyth_program_id;
+        oracle_info.pyth_product_pubkey = market.pyth_product;
+        oracle_info.pyth_price_pubkey = market.pyth_price;
+        configure(&mut oracle_info);
+        add_account(&mut program_test, market.oracle, pack(oracle_info), program_id);
+
+        let usdi_supply = pools.iter().map(|pool| pool.usdi_amount).sum::<u64>() + user_usdi_amount + 2 * POOL_COLLATERAL;
+        add_account(&mut program_test, usdi_mint, mint_data(Pubkey::new_unique(), usdi_supply, USDI_DECIMALS), spl_token::id());
+        add_account(&mut program_test, market.fee_token_account, token_account_data(usdi_mint, oracle_pda, 0), spl_token::id());
+        add_account(
+            &mut program_test,
+            market.user_usdi_token_account,
+            token_account_data(usdi_mint, market.user.pubkey(), user_usdi_amount),
+            spl_token::id(),
+        );
+        add_account(
+            &mut program_test,
+            market.keeper_reward_token_account,
+            token_account_data(usdi_mint, Pubkey::new_unique(), 0),
+            spl_token::id(),
+        );
+
+        add_account(&mut program_test, market.ucp_account, Vec::new(), ucp_program_id);
+        add_account(&mut program_test, market.lpcp_account, Vec::new(), lpcp_program_id);
+        add_account(
+            &mut program_test,
+            market.ucp_collateral,
+            token_account_data(usdi_mint, market.ucp_pda, POOL_COLLATERAL),
+            spl_token::id(),
+        );
+        add_account(
+            &mut program_test,
+            market.lpcp_collateral,
+            token_account_data(usdi_mint, market.lpcp_pda, POOL_COLLATERAL),
+            spl_token::id(),
+        );
+
+        for (amm, pool) in market.amms.iter().zip(pools) {
+            let amm_info = AMM {
+                is_initialized: true,
+                usdi_token_account_pubkey: amm.usdi_token_account,
+                iasset_token_account_pubkey: amm.iasset_token_account,
+                oracle_pda_token_account_pubkey: Pubkey::default(),
+                lpcp_pda_token_account_pubkey: Pubkey::default(),
+                ucp_pda_token_account_pubkey: Pubkey::default(),
+                last_trade_spread: 0,
+            };
+            add_account(&mut program_test, amm.amm_account, pack(amm_info), amm_program_id);
+
+            let curve_config = CurveConfig {
+                is_initialized: true,
+                oracle_pubkey: market.oracle,
+                amm_pubkey: amm.amm_account,
+                curve_type: 0,
+                amplification: 0,
+                last_correction_slot: 0,
+                last_correction_price: 0,
+            };
+            add_account(&mut program_test, amm.curve_config, pack(curve_config), program_id);
+
+            add_account(&mut program_test, amm.iasset_mint, mint_data(market.amm_pda, pool.iasset_supply, pool.iasset_decimals), spl_token::id());
+            add_account(
+                &mut program_test,
+                amm.usdi_token_account,
+                token_account_data(usdi_mint, market.amm_pda, pool.usdi_amount),
+                spl_token::id(),
+            );
+            add_account(
+                &mut program_test,
+                amm.iasset_token_account,
+                token_account_data(amm.iasset_mint, market.amm_pda, pool.iasset_amount),
+                spl_token::id(),
+            );
+            add_account(
+                &mut program_test,
+                amm.user_iasset_token_account,
+                token_account_data(amm.iasset_mint, market.user.pubkey(), pool.user_iasset_amount),
+                spl_token::id(),
+            );
+        }
+
+        (market, program_test)
+    }
+
+    /// Replaces the Pyth price account with one priced at {pyth_price} ± {conf}, published at {pub_slot}
+    pub fn set_pyth_price(&self, program_test: &mut ProgramTest, pyth_price: i64, conf: u64, pub_slot: u64) {
+        add_account(program_test, self.pyth_price, pyth_price_data_with_conf(pyth_price, conf, pub_slot), self.pyth_program_id);
+    }
+
+    /// Replaces the fee token account with one holding {amount} of collected fees
+    pub fn set_fee_balance(&self, program_test: &mut ProgramTest, amount: u64) {
+        add_account(program_test, self.fee_token_account, token_account_data(self.usdi_mint, self.oracle_pda, amount), spl_token::id());
+    }
+
+    /// The programs the Oracle invokes while trading
+    fn trade_programs(&self) -> Vec<AccountMeta> {
+        vec![
+            AccountMeta::new_readonly(self.amm_program_id, false),
+            AccountMeta::new_readonly(self.ucp_program_id, false),
+        ]
+    }
+
+    fn trade(&self, amm_index: usize, data: OracleInstruction) -> Instruction {
+        let amm = &self.amms[amm_index];
+        let mut accounts = vec![
+            AccountMeta::new(self.fee_token_account, false),
+            AccountMeta::new(self.oracle, false),
+            AccountMeta::new_readonly(self.user.pubkey(), true),
+            AccountMeta::new(self.user_usdi_token_account, false),
+            AccountMeta::new(amm.user_iasset_token_account, false),
+            AccountMeta::new(amm.usdi_token_account, false),
+            AccountMeta::new(amm.iasset_token_account, false),
+            AccountMeta::new(amm.amm_account, false),
+            AccountMeta::new_readonly(spl_token::id(), false),
+            AccountMeta::new_readonly(self.amm_pda, false),
+            AccountMeta::new(self.ucp_account, false),
+            AccountMeta::new(self.ucp_collateral, false),
+            AccountMeta::new(self.lpcp_collateral, false),
+            AccountMeta::new_readonly(self.ucp_pda, false),
+            AccountMeta::new(self.oracle_pda, false),
+        ];
+        accounts.extend([
+            AccountMeta::new_readonly(self.pyth_product, false),
+            AccountMeta::new_readonly(self.pyth_price, false),
+            AccountMeta::new_readonly(self.usdi_mint, false),
+            AccountMeta::new_readonly(amm.iasset_mint, false),
+        ]);
+        accounts.extend(self.trade_programs());
+        Instruction {
+            program_id: self.program_id,
+            accounts,
+            data: data.pack(),
+        }
+    }
+
+    pub fn trade_usdi(&self, amm_index: usize, amount: u64, min_amount_out: u64, expiry_slot: Option<u64>) -> Instruction {
+        self.trade(amm_index, OracleInstruction::TradeUSDI { amount, min_amount_out, expiry_slot })
+    }
+
+    pub fn trade_iasset(&self, amm_index: usize, amount: u64, min_amount_out: u64, expiry_slot: Option<u64>) -> Instruction {
+        self.trade(amm_index, OracleInstruction::TradeiAsset { amount, min_amount_out, expiry_slot })
+    }
+
+    pub fn trade_route(&self, sell_index: usize, buy_index: usize, amount: u64, min_amount_out: u64, expiry_slot: Option<u64>) -> Instruction {
+        let (sell_amm, buy_amm) = (&self.amms[sell_index], &self.amms[buy_index]);
+        let mut accounts = vec![
+            AccountMeta::new(self.fee_token_account, false),
+            AccountMeta::new(self.oracle, false),
+            AccountMeta::new_readonly(self.user.pubkey(), true),
+            AccountMeta::new(self.user_usdi_token_account, false),
+            AccountMeta::new(sell_amm.user_iasset_token_account, false),
+            AccountMeta::new(buy_amm.user_iasset_token_account, false),
+            AccountMeta::new_readonly(spl_token::id(), false),
+            AccountMeta::new(self.ucp_account, false),
+            AccountMeta::new(self.ucp_collateral, false),
+            AccountMeta::new(self.lpcp_collateral, false),
+            AccountMeta::new_readonly(self.ucp_pda, false),
+            AccountMeta::new(self.oracle_pda, false),
+        ];
+        for amm in [sell_amm, buy_amm] {
+            accounts.extend([
+                AccountMeta::new(amm.usdi_token_account, false),
+                AccountMeta::new(amm.iasset_token_account, false),
+                AccountMeta::new(amm.amm_account, false),
+                AccountMeta::new_readonly(self.amm_pda, false),
+            ]);
+        }
+        accounts.extend([
+            AccountMeta::new_readonly(self.pyth_product, false),
+            AccountMeta::new_readonly(self.pyth_price, false),
+            AccountMeta::new_readonly(self.usdi_mint, false),
+            AccountMeta::new_readonly(sell_amm.iasset_mint, false),
+            AccountMeta::new_readonly(buy_amm.iasset_mint, false),
+        ]);
+        accounts.extend(self.trade_programs());
+        Instruction {
+            program_id: self.program_id,
+            accounts,
+            data: OracleInstruction::TradeRoute { amount, min_amount_out, expiry_slot }.pack(),
+        }
+    }
+
+    /// Corrects the collateral of the AMMs at {amm_indices}, paying {keeper} from the fee token account
+    pub fn collateral_correction(&self, amm_indices: &[usize], keeper: &Pubkey) -> Instruction {
+        let mut accounts = vec![
+            AccountMeta::new_readonly(self.pyth_product, false),
+            AccountMeta::new_readonly(self.pyth_price, false),
+            AccountMeta::new(self.fee_token_account, false),
+            AccountMeta::new(self.oracle, false),
+            AccountMeta::new_readonly(spl_token::id(), false),
+            AccountMeta::new_readonly(self.amm_pda, false),
+            AccountMeta::new(self.lpcp_collateral, false),
+            AccountMeta::new(self.ucp_collateral, false),
+            AccountMeta::new(self.lpcp_account, false),
+            AccountMeta::new(self.ucp_account, false),
+            AccountMeta::new_readonly(self.lpcp_pda, false),
+            AccountMeta::new_readonly(self.ucp_pda, false),
+            AccountMeta::new_readonly(*keeper, true),
+            AccountMeta::new(self.keeper_reward_token_account, false),
+            AccountMeta::new(self.oracle_pda, false),
+            AccountMeta::new_readonly(self.usdi_mint, false),
+        ];
+        for &amm_index in amm_indices {
+            let amm = &self.amms[amm_index];
+            accounts.extend([
+                AccountMeta::new(amm.amm_account, false),
+                AccountMeta::new(amm.usdi_token_account, false),
+                AccountMeta::new(amm.iasset_token_account, false),
+                AccountMeta::new(amm.iasset_mint, false),
+                AccountMeta::new(amm.curve_config, false),
+            ]);
+        }
+        accounts.extend([
+            AccountMeta::new_readonly(self.amm_program_id, false),
+            AccountMeta::new_readonly(self.ucp_program_id, false),
+            AccountMeta::new_readonly(self.lpcp_program_id, false),
+        ]);
+        Instruction {
+            program_id: self.program_id,
+            accounts,
+            data: OracleInstruction::CollateralCorrection { num_amms: amm_indices.len() as u64 }.pack(),
+        }
+    }
+
+    /// Selects the curve of the AMM at {amm_index}, signed by the admin
+    pub fn set_curve(&self, amm_index: usize, curve_type: u8, amplification: u64) -> Instruction {
+        let amm = &self.amms[amm_index];
+        Instruction {
+            program_id: self.program_id,
+            accounts: vec![
+                AccountMeta::new_readonly(self.admin.pubkey(), true),
+                AccountMeta::new_readonly(self.oracle, false),
+                AccountMeta::new(amm.curve_config, false),
+                AccountMeta::new_readonly(sysvar::rent::id(), false),
+                AccountMeta::new_readonly(amm.amm_account, false),
+            ],
+            data: OracleInstruction::SetCurve { curve_type, amplification }.pack(),
+        }
+    }
+
+    /// Initializes the Oracle account, which must be stored zeroed, signed by {initializer}
+    pub fn init_oracle(&self, initializer: &Pubkey) -> Instruction {
+        Instruction {
+            program_id: self.program_id,
+            accounts: vec![
+                AccountMeta::new_readonly(*initializer, true),
+                AccountMeta::new(self.fee_token_account, false),
+                AccountMeta::new(self.oracle, false),
+                AccountMeta::new_readonly(sysvar::rent::id(), false),
+                AccountMeta::new_readonly(spl_token::id(), false),
+                AccountMeta::new_readonly(self.pyth_product, false),
+                AccountMeta::new_readonly(self.pyth_price, false),
+            ],
+            data: OracleInstruction::InitOracle {
+                amm_program_id: self.amm_program_id,
+                lpcp_program_id: self.lpcp_program_id,
+                ucp_program_id: self.ucp_program_id,
+                pyth_program_id: self.pyth_program_id,
+                oracle_band_bps: MARKET_ORACLE_BAND_BPS,
+                trade_fee_bps: 30,
+                keeper_reward: 0,
+                keeper_reward_interval_slots: 0,
+                min_correction_interval_slots: 0,
+                correction_override_bps: 0,
+                max_correction_amount: 0,
+                max_correction_bps: 0,
+            }
+            .pack(),
+        }
+    }
+
+    pub fn quote(&self, amm_index: usize, amount: u64, usdi_in: bool) -> Instruction {
+        let amm = &self.amms[amm_index];
+        Instruction {
+            program_id: self.program_id,
+            accounts: vec![
+                AccountMeta::new_readonly(self.oracle, false),
+                AccountMeta::new_readonly(self.pyth_product, false),
+                AccountMeta::new_readonly(self.pyth_price, false),
+                AccountMeta::new_readonly(amm.amm_account, false),
+                AccountMeta::new_readonly(amm.usdi_token_account, false),
+                AccountMeta::new_readonly(amm.iasset_token_account, false),
+                AccountMeta::new_readonly(amm.curve_config, false),
+                AccountMeta::new_readonly(self.usdi_mint, false),
+                AccountMeta::new_readonly(amm.iasset_mint, false),
+            ],
+            data: OracleInstruction::Quote { amount, usdi_in }.pack(),
+        }
+    }
+
+    pub fn get_price(&self) -> Instruction {
+        Instruction {
+            program_id: self.program_id,
+            accounts: vec![
+                AccountMeta::new_readonly(self.oracle, false),
+                AccountMeta::new_readonly(self.pyth_product, false),
+                AccountMeta::new_readonly(self.pyth_price, false),
+            ],
+            data: OracleInstruction::GetPrice.pack(),
+        }
+    }
+
+    pub fn set_pause(&self, admin: &Pubkey, paused_flags: u8) -> Instruction {
+        Instruction {
+            program_id: self.program_id,
+            accounts: vec![
+                AccountMeta::new_readonly(*admin, true),
+                AccountMeta::new(self.oracle, false),
+            ],
+            data: OracleInstruction::SetPause { paused_flags }.pack(),
+        }
+    }
+
+    pub fn withdraw_fees(&self, admin: &Pubkey, destination: Pubkey, amount: u64) -> Instruction {
+        Instruction {
+            program_id: self.program_id,
+            accounts: vec![
+                AccountMeta::new_readonly(*admin, true),
+                AccountMeta::new_readonly(self.oracle, false),
+                AccountMeta::new(self.fee_token_account, false),
+                AccountMeta::new(destination, false),
+                AccountMeta::new_readonly(spl_token::id(), false),
+                AccountMeta::new_readonly(self.oracle_pda, false),
+            ],
+            data: OracleInstruction::WithdrawFees { amount }.pack(),
+        }
+    }
+}
+
+/// Processes {instruction}, signed by the payer and {signers}, and returns the error it fails with
+pub async fn process(
+    banks_client: &mut BanksClient,
+    payer: &Keypair,
+    instruction: Instruction,
+    signers: &[&Keypair],
+) -> Result<(), TransactionError> {
+    let recent_blockhash = banks_client.get_latest_blockhash().await.unwrap();
+    let mut all_signers = vec![payer];
+    all_signers.extend_from_slice(signers);
+    let transaction = Transaction::new_signed_with_payer(&[instruction], Some(&payer.pubkey()), &all_signers, recent_blockhash);
+    banks_client.process_transaction(transaction).await.map_err(|err| err.unwrap())
+}
@@ -0,0 +1,143 @@
+//! Account builders shared by the program tests
+#![allow(dead_code, unused_imports)]
+
+use oracle_program::{error::OracleError, states::Oracle};
+use pyth_client::{AccountType, PriceStatus};
+use solana_program::{instruction::Instruction, program_option::COption, program_pack::Pack, pubkey::Pubkey};
+use solana_program_test::{BanksClient, ProgramTest};
+use solana_sdk::{
+    account::Account,
+    instruction::InstructionError,
+    signature::{Keypair, Signer},
+    transaction::{Transaction, TransactionError},
+};
+use spl_token::state::{Account as TokenAccount, AccountState, Mint};
+use std::mem::size_of;
+
+mod market;
+mod stand_in;
+
+pub use market::*;
+pub use stand_in::*;
+
+pub const PYTH_EXPO: i32 = -8;
+pub const USDI_DECIMALS: u8 = 6;
+
+/// Collateral each pool starts with, in USDI base units
+pub const POOL_COLLATERAL: u64 = 1_000_000_000_000;
+
+pub fn add_account(program_test: &mut ProgramTest, pubkey: Pubkey, data: Vec<u8>, owner: Pubkey) {
+    program_test.add_account(
+        pubkey,
+        Account {
+            lamports: 1_000_000_000,
+            data,
+            owner,
+            executable: false,
+            rent_epoch: 0,
+        },
+    );
+}
+
+pub fn pack<T: Pack>(value: T) -> Vec<u8> {
+    let mut data = vec![0; T::LEN];
+    T::pack(value, &mut data).unwrap();
+    data
+}
+
+pub fn token_account_data(mint: Pubkey, owner: Pubkey, amount: u64) -> Vec<u8> {
+    pack(TokenAccount {
+        mint,
+        owner,
+        amount,
+        delegate: COption::None,
+        state: AccountState::Initialized,
+        is_native: COption::None,
+        delegated_amount: 0,
+        close_authority: COption::None,
+    })
+}
+
+pub fn mint_data(mint_authority: Pubkey, supply: u64, decimals: u8) -> Vec<u8> {
+    pack(Mint {
+        mint_authority: COption::Some(mint_authority),
+        supply,
+        decimals,
+        is_initialized: true,
+        freeze_authority: COption::None,
+    })
+}
+
+/// {amount} whole tokens in the base units of a mint with {decimals}
+pub fn units(amount: u64, decimals: u8) -> u64 {
+    amount * 10u64.pow(u32::from(decimals))
+}
+
+pub fn as_bytes<T>(value: &T) -> Vec<u8> {
+    unsafe { std::slice::from_raw_parts(value as *const T as *const u8, size_of::<T>()) }.to_vec()
+}
+
+pub fn pyth_product_data(pyth_price: &Pubkey) -> Vec<u8> {
+    let mut product: pyth_client::Product = unsafe { std::mem::zeroed() };
+    product.magic = pyth_client::MAGIC;
+    product.ver = pyth_client::VERSION_2;
+    product.atype = AccountType::Product as u32;
+    product.px_acc.val = pyth_price.to_bytes();
+    as_bytes(&product)
+}
+
+pub fn pyth_price_data(pyth_price: i64) -> Vec<u8> {
+    pyth_price_data_with_conf(pyth_price, 0, 0)
+}
+
+pub fn pyth_price_data_with_conf(pyth_price: i64, conf: u64, pub_slot: u64) -> Vec<u8> {
+    let mut price: pyth_client::Price = unsafe { std::mem::zeroed() };
+    price.magic = pyth_client::MAGIC;
+    price.ver = pyth_client::VERSION_2;
+    price.atype = AccountType::Price as u32;
+    price.expo = PYTH_EXPO;
+    price.agg.price = pyth_price;
+    price.agg.conf = conf;
+    price.agg.pub_slot = pub_slot;
+    price.agg.status = PriceStatus::Trading;
+    as_bytes(&price)
+}
+
+/// The error of the first instruction failing with {err}
+pub fn oracle_error(err: OracleError) -> TransactionError {
+    TransactionError::InstructionError(0, InstructionError::Custom(err as u32))
+}
+
+pub async fn token_amount(banks_client: &mut BanksClient, token_account: Pubkey) -> u64 {
+    let data = banks_client.get_account(token_account).await.unwrap().unwrap().data;
+    TokenAccount::unpack(&data).unwrap().amount
+}
+
+/// An initialized Oracle with every limit, fee and reward switched off
+pub fn new_oracle(bump_seed: u8) -> Oracle {
+    let mut oracle_info = Oracle::unpack_unchecked(&[0; Oracle::LEN]).unwrap();
+    oracle_info.is_initialized = true;
+    oracle_info.bump_seed = bump_seed;
+    oracle_info
+}
+
+/// Simulates {instruction} and returns its return data, or the error it fails with
+pub async fn simulate_return_data(
+    banks_client: &mut BanksClient,
+    payer: &Keypair,
+    instruction: Instruction,
+) -> Result<Vec<u8>, TransactionError> {
+    let recent_blockhash = banks_client.get_latest_blockhash().await.unwrap();
+    let transaction = Transaction::new_signed_with_payer(&[instruction], Some(&payer.pubkey()), &[payer], recent_blockhash);
+    let simulation = banks_client.simulate_transaction(transaction).await.unwrap();
+    simulation.result.unwrap()?;
+    Ok(simulation
+        .simulation_details
+        .and_then(|details| details.return_data)
+        .map(|return_data| return_data.data)
+        .unwrap_or_default())
+}
+
+pub async fn oracle_info(banks_client: &mut BanksClient, oracle: Pubkey) -> Oracle {
+    Oracle::unpack(&banks_client.get_account(oracle).await.unwrap().unwrap().data).unwrap()
+}
@@ -0,0 +1,122 @@
+//! Stand-in AMM, UCP and LPCP programs, moving real tokens through the token program
+
+use oracle_program::{curve::calc_sell_price_from_num_iasset, states::AMM};
+use solana_program::{
+    account_info::AccountInfo,
+    entrypoint::ProgramResult,
+    program::{invoke, invoke_signed},
+    program_error::ProgramError,
+    program_pack::Pack,
+    pubkey::Pubkey,
+};
+use spl_token::state::Account as TokenAccount;
+use std::convert::TryInto;
+
+/// Seed of the stand-in AMM's PDA, which owns every AMM's token accounts and iAsset mint
+pub const AMM_PDA_SEED: &[u8] = b"amm";
+
+/// Seed of the stand-in UCP's and LPCP's PDA, which owns their collateral token account
+pub const POOL_PDA_SEED: &[u8] = b"pool";
+
+pub fn find_amm_pda(amm_program_id: &Pubkey) -> Pubkey {
+    Pubkey::find_program_address(&[AMM_PDA_SEED], amm_program_id).0
+}
+
+pub fn find_pool_pda(pool_program_id: &Pubkey) -> Pubkey {
+    Pubkey::find_program_address(&[POOL_PDA_SEED], pool_program_id).0
+}
+
+/// Which stand-in AMM program a market runs
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum StandInAmm {
+    /// Trades on the constant product and mints and burns iAsset, `process_stand_in_amm`
+    Trading,
+    /// Accepts every instruction and leaves its balances unchanged, `process_noop_amm`
+    Noop,
+}
+
+/// Accepts every instruction and leaves its balances unchanged
+pub fn process_noop_amm(_program_id: &Pubkey, _accounts: &[AccountInfo], _data: &[u8]) -> ProgramResult {
+    Ok(())
+}
+
+/// Stand-in AMM that trades on the constant product, recording each trade's spread,
+/// and mints and burns iAsset in its token account
+pub fn process_stand_in_amm(program_id: &Pubkey, accounts: &[AccountInfo], data: &[u8]) -> ProgramResult {
+    if data.len() != 9 || !accounts[0].is_signer {
+        return Err(ProgramError::InvalidInstructionData);
+    }
+    let amount = u64::from_le_bytes(data[1..9].try_into().unwrap());
+    match data[0] {
+        3 => trade(program_id, accounts, true, amount),
+        4 => trade(program_id, accounts, false, amount),
+        5 | 6 => change_supply(program_id, accounts, data[0] == 5, amount),
+        _ => Err(ProgramError::InvalidInstructionData),
+    }
+}
+
+fn trade(program_id: &Pubkey, accounts: &[AccountInfo], usdi_in: bool, amount: u64) -> ProgramResult {
+    let (user, user_usdi, user_iasset) = (&accounts[1], &accounts[2], &accounts[3]);
+    let (amm_usdi, amm_iasset, amm_account, token_program, amm_pda) = (&accounts[4], &accounts[5], &accounts[6], &accounts[7], &accounts[8]);
+
+    let usdi_amm_amount = TokenAccount::unpack(&amm_usdi.data.borrow())?.amount;
+    let iasset_amm_amount = TokenAccount::unpack(&amm_iasset.data.borrow())?.amount;
+    let (user_source, amm_destination, amm_source, user_destination, amount_out) = if usdi_in {
+        (user_usdi, amm_usdi, amm_iasset, user_iasset, calc_sell_price_from_num_iasset(iasset_amm_amount, usdi_amm_amount, amount))
+    } else {
+        (user_iasset, amm_iasset, amm_usdi, user_usdi, calc_sell_price_from_num_iasset(usdi_amm_amount, iasset_amm_amount, amount))
+    };
+
+    //The spread is the USDI value, at the price before the trade, the user loses to the curve
+    let to_usdi = |iasset: u64| (iasset as u128 * usdi_amm_amount as u128 / iasset_amm_amount as u128) as u64;
+    let spread = if usdi_in { amount - to_usdi(amount_out) } else { to_usdi(amount) - amount_out };
+    let mut amm_info = AMM::unpack(&amm_account.data.borrow())?;
+    amm_info.last_trade_spread = spread;
+    AMM::pack(amm_info, &mut amm_account.data.borrow_mut())?;
+
+    invoke(
+        &spl_token::instruction::transfer(token_program.key, user_source.key, amm_destination.key, user.key, &[], amount)?,
+        &[user_source.clone(), amm_destination.clone(), user.clone(), token_program.clone()],
+    )?;
+    let (_, bump_seed) = Pubkey::find_program_address(&[AMM_PDA_SEED], program_id);
+    invoke_signed(
+        &spl_token::instruction::transfer(token_program.key, amm_source.key, user_destination.key, amm_pda.key, &[], amount_out)?,
+        &[amm_source.clone(), user_destination.clone(), amm_pda.clone(), token_program.clone()],
+        &[&[AMM_PDA_SEED, &[bump_seed]]],
+    )
+}
+
+fn change_supply(program_id: &Pubkey, accounts: &[AccountInfo], mint: bool, amount: u64) -> ProgramResult {
+    let (amm_iasset_token_account, iasset_mint, token_program, amm_pda) = (&accounts[1], &accounts[2], &accounts[4], &accounts[5]);
+    let instruction = if mint {
+        spl_token::instruction::mint_to(token_program.key, iasset_mint.key, amm_iasset_token_account.key, amm_pda.key, &[], amount)?
+    } else {
+        spl_token::instruction::burn(token_program.key, amm_iasset_token_account.key, iasset_mint.key, amm_pda.key, &[], amount)?
+    };
+    let (_, bump_seed) = Pubkey::find_program_address(&[AMM_PDA_SEED], program_id);
+    invoke_signed(
+        &instruction,
+        &[amm_iasset_token_account.clone(), iasset_mint.clone(), amm_pda.clone(), token_program.clone()],
+        &[&[AMM_PDA_SEED, &[bump_seed]]],
+    )
+}
+
+/// Stand-in UCP and LPCP that move {amount} of collateral from their collateral
+/// token account, owned by the pool PDA, to the other pool's
+pub fn process_collateral_pool(program_id: &Pubkey, accounts: &[AccountInfo], data: &[u8]) -> ProgramResult {
+    if data.len() != 9 || data[0] != 3 {
+        return Err(ProgramError::InvalidInstructionData);
+    }
+    if !accounts[0].is_signer {
+        return Err(ProgramError::MissingRequiredSignature);
+    }
+
+    let amount = u64::from_le_bytes(data[1..9].try_into().unwrap());
+    let (source, destination, token_program, pool_pda) = (&accounts[1], &accounts[2], &accounts[4], &accounts[5]);
+    let (_, bump_seed) = Pubkey::find_program_address(&[POOL_PDA_SEED], program_id);
+    invoke_signed(
+        &spl_token::instruction::transfer(token_program.key, source.key, destination.key, pool_pda.key, &[], amount)?,
+        &[source.clone(), destination.clone(), pool_pda.clone(), token_program.clone()],
+        &[&[POOL_PDA_SEED, &[bump_seed]]],
+    )
+}
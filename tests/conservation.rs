@@ -0,0 +1,249 @@
+use oracle_program::{
+    curve::{calc_sell_price_from_num_iasset, curve_from_config, SwapDecimals},
+    instruction::OracleInstruction,
+    math::{Decimal, Rounding},
+    processor::{calc_lpr, to_u64, AmmBalances, Processor},
+    simulator::Simulation,
+    states::{CurveConfig, Oracle, AMM},
+};
+use proptest::prelude::*;
+use solana_program::{account_info::AccountInfo, program_pack::Pack, pubkey::Pubkey};
+use spl_token::state::{Account as TokenAccount, AccountState};
+
+/// A step of the simulated market; trades carry no fee, so USDI is conserved exactly
+#[derive(Clone, Debug)]
+enum Step {
+    Buy(u64),
+    Sell(u64),
+    Tick(u64),
+}
+
+fn step() -> impl Strategy<Value = Step> {
+    prop_oneof![
+        (1..1_000_000_000_000u64).prop_map(Step::Buy),
+        (1..1_000_000_000_000u64).prop_map(Step::Sell),
+        //Oracle prices from 0.01 to 10,000 USDI, in hundredths
+        (1..1_000_000u64).prop_map(Step::Tick),
+    ]
+}
+
+fn balances() -> impl Strategy<Value = AmmBalances> {
+    (
+        1_000_000..1_000_000_000_000_000u64,
+        1_000_000..1_000_000_000_000_000u64,
+        0..1_000_000_000_000u64,
+        0..=9u32,
+        0..=9u32,
+    )
+        .prop_map(|(usdi_amount, iasset_amount, user_owned_iasset, usdi_decimals, iasset_decimals)| AmmBalances {
+            usdi_amount,
+            iasset_amount,
+            iasset_supply: iasset_amount + user_owned_iasset,
+            usdi_decimals,
+            iasset_decimals,
+        })
+}
+
+/// Random contents at {len}, with the leading is_initialized flag set so unpacking gets past it
+fn initialized_data(len: usize) -> impl Strategy<Value = Vec<u8>> {
+    prop::collection::vec(any::<u8>(), len).prop_map(|mut data| {
+        data[0] = 1;
+        data
+    })
+}
+
+/// Account data for the processor fuzz, and whether the account is owned by the
+/// program rather than the token program: mostly the program's own accounts at
+/// their packed length with random fields, so instructions get past unpacking
+fn account_data() -> impl Strategy<Value = (Vec<u8>, bool)> {
+    prop_oneof![
+        initialized_data(Oracle::LEN).prop_map(|data| (data, true)),
+        initialized_data(CurveConfig::LEN).prop_map(|data| (data, true)),
+        initialized_data(AMM::LEN).prop_map(|data| (data, true)),
+        (any::<[u8; 32]>(), any::<[u8; 32]>(), any::<u64>()).prop_map(|(mint, owner, amount)| {
+            let mut data = vec![0; TokenAccount::LEN];
+            TokenAccount::pack(
+                TokenAccount {
+                    mint: Pubkey::new_from_array(mint),
+                    owner: Pubkey::new_from_array(owner),
+                    amount,
+                    state: AccountState::Initialized,
+                    ..TokenAccount::default()
+                },
+                &mut data,
+            )
+            .unwrap();
+            (data, false)
+        }),
+        (prop::collection::vec(any::<u8>(), 0..512), any::<bool>()),
+    ]
+}
+
+proptest! {
+    #[test]
+    fn simulated_trades_and_corrections_conserve_usdi_and_supply(
+        balances in balances(),
+        curve_type in 0..=1u8,
+        steps in prop::collection::vec(step(), 1..32),
+    ) {
+        let mut simulation = Simulation::new(balances).unwrap();
+        simulation.set_curve(curve_type, 100).unwrap();
+        simulation.set_caps(0, 2_000);
+
+        //An independent ledger of the market, moved by each step's inputs and the curve's own quotes
+        let curve = curve_from_config(curve_type, 100).unwrap();
+        let mut amm = balances;
+        let mut user_iasset = balances.iasset_supply - balances.iasset_amount;
+        let mut user_usdi: i128 = 0;
+
+        for step in steps {
+            match step {
+                Step::Buy(usdi_amount) => {
+                    let decimals = SwapDecimals::new(amm.iasset_decimals, amm.usdi_decimals);
+                    let iasset_out = curve.sell_price(amm.iasset_amount, amm.usdi_amount, usdi_amount, decimals);
+                    if simulation.buy(usdi_amount).is_ok() {
+                        let iasset_out = iasset_out.unwrap();
+                        amm.usdi_amount += usdi_amount;
+                        amm.iasset_amount -= iasset_out;
+                        user_iasset += iasset_out;
+                        user_usdi -= usdi_amount as i128;
+                    }
+                }
+                Step::Sell(iasset_amount) => {
+                    let decimals = SwapDecimals::new(amm.usdi_decimals, amm.iasset_decimals);
+                    let usdi_out = curve.sell_price(amm.usdi_amount, amm.iasset_amount, iasset_amount, decimals);
+                    let result = simulation.sell(iasset_amount);
+                    if iasset_amount > user_iasset {
+                        prop_assert!(result.is_err());
+                    } else if result.is_ok() {
+                        let usdi_out = usdi_out.unwrap();
+                        amm.usdi_amount -= usdi_out;
+                        amm.iasset_amount += iasset_amount;
+                        user_iasset -= iasset_amount;
+                        user_usdi += usdi_out as i128;
+                    }
+                }
+                Step::Tick(price_hundredths) => {
+                    let price = Decimal::from(price_hundredths).try_div_u64(100).unwrap();
+                    let lpcp_collateral = simulation.lpcp_collateral;
+                    if let Ok(report) = simulation.tick(price) {
+                        prop_assert!(report.minted == 0 || report.burned == 0);
+                        amm.iasset_amount = amm.iasset_amount + report.minted - report.burned;
+                        amm.iasset_supply = amm.iasset_supply + report.minted - report.burned;
+                        prop_assert_eq!(
+                            simulation.lpcp_collateral - lpcp_collateral,
+                            report.ucp_to_lpcp as i128 - report.lpcp_to_ucp as i128
+                        );
+                    }
+                }
+            }
+
+            prop_assert_eq!(simulation.balances, amm);
+            prop_assert_eq!(amm.iasset_supply, amm.iasset_amount + user_iasset);
+            prop_assert_eq!(amm.usdi_amount as i128 + user_usdi, balances.usdi_amount as i128);
+            prop_assert_eq!(simulation.ucp_collateral + simulation.lpcp_collateral, 0);
+        }
+    }
+
+    #[test]
+    fn corrections_move_collateral_by_the_change_in_lpr(balances in balances(), price_hundredths in 1..1_000_000u64) {
+        let mut simulation = Simulation::new(balances).unwrap();
+        let price = Decimal::from(price_hundredths).try_div_u64(100).unwrap();
+        if let Ok(report) = simulation.tick(price) {
+            let lpr_before = report.lpr_before.try_to_base_units(balances.usdi_decimals).unwrap();
+            let lpr_after = report.lpr_after.try_to_base_units(balances.usdi_decimals).unwrap();
+            let lpr_change = to_u64(lpr_before, Rounding::RoundDown).unwrap() as i128 - to_u64(lpr_after, Rounding::RoundDown).unwrap() as i128;
+            prop_assert_eq!(report.ucp_to_lpcp as i128 - report.lpcp_to_ucp as i128, lpr_change);
+            prop_assert_eq!(simulation.lpcp_collateral, lpr_change);
+        }
+    }
+
+    #[test]
+    fn selling_then_buying_back_never_gains_iasset(x in 1..u64::MAX / 2, y in 1..u64::MAX / 2, n in 0..u64::MAX / 2) {
+        let usdi_out = calc_sell_price_from_num_iasset(x, y, n);
+        let iasset_back = calc_sell_price_from_num_iasset(y + n, x - usdi_out, usdi_out);
+        prop_assert!(iasset_back <= n);
+    }
+
+    #[test]
+    fn preferred_lpr_is_below_and_monotonic_in_price(price in any::<u64>(), delta in any::<u64>()) {
+        let lpr = calc_lpr(Decimal::from(price)).unwrap();
+        prop_assert!(lpr <= Decimal::from(price));
+        prop_assert!(lpr <= calc_lpr(Decimal::from(price).try_add(Decimal::from(delta)).unwrap()).unwrap());
+    }
+
+    #[test]
+    fn to_u64_brackets_the_value(scaled_val in any::<u128>()) {
+        let value = Decimal::from_scaled_val(scaled_val);
+        match (to_u64(value, Rounding::RoundDown), to_u64(value, Rounding::RoundUp)) {
+            (Ok(down), Ok(up)) => {
+                prop_assert!(Decimal::from(down) <= value && value <= Decimal::from(up));
+                prop_assert!(up - down <= 1);
+            }
+            (Ok(down), Err(_)) => prop_assert_eq!(down, u64::MAX),
+            (Err(_), up) => prop_assert!(up.is_err()),
+        }
+    }
+
+    #[test]
+    fn unpacking_arbitrary_instruction_data_never_panics(data in prop::collection::vec(any::<u8>(), 0..256)) {
+        let _ = OracleInstruction::unpack(&data);
+    }
+
+    #[test]
+    fn processing_arbitrary_instructions_never_panics(
+        data in prop::collection::vec(any::<u8>(), 0..256),
+        accounts in prop::collection::vec((account_data(), any::<bool>(), any::<bool>()), 0..32),
+    ) {
+        let program_id = Pubkey::new_unique();
+        let mut accounts: Vec<(Pubkey, Pubkey, u64, Vec<u8>, bool, bool)> = accounts
+            .into_iter()
+            .map(|((data, owned_by_program), is_signer, is_writable)| {
+                let owner = if owned_by_program { program_id } else { spl_token::id() };
+                (Pubkey::new_unique(), owner, 1_000_000, data, is_signer, is_writable)
+            })
+            .collect();
+        let account_infos: Vec<AccountInfo> = accounts
+            .iter_mut()
+            .map(|(key, owner, lamports, data, is_signer, is_writable)| {
+                AccountInfo::new(key, *is_signer, *is_writable, lamports, data, owner, false, 0)
+            })
+            .collect();
+        let _ = Processor::process(&program_id, &account_infos, &data);
+    }
+
+    #[test]
+    fn extreme_balances_never_panic(
+        usdi_amount in any::<u64>(),
+        iasset_amount in any::<u64>(),
+        iasset_supply in any::<u64>(),
+        decimals in (any::<u8>(), any::<u8>()),
+        price in any::<u64>(),
+        curve_type in any::<u8>(),
+        amplification in any::<u64>(),
+    ) {
+        let mut simulation = Simulation::new(AmmBalances {
+            usdi_amount,
+            iasset_amount,
+            iasset_supply,
+            usdi_decimals: u32::from(decimals.0),
+            iasset_decimals: u32::from(decimals.1),
+        })
+        .unwrap();
+        let _ = simulation.set_curve(curve_type, amplification);
+
+        //A failed step leaves the balances untouched, like a failed transaction
+        let before = simulation.balances;
+        if simulation.tick(Decimal::from(price)).is_err() {
+            prop_assert_eq!(simulation.balances, before);
+        }
+        let before = simulation.balances;
+        if simulation.buy(usdi_amount).is_err() {
+            prop_assert_eq!(simulation.balances, before);
+        }
+        let before = simulation.balances;
+        if simulation.sell(iasset_amount).is_err() {
+            prop_assert_eq!(simulation.balances, before);
+        }
+    }
+}
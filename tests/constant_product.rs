@@ -0,0 +1,52 @@
+use num_bigint::BigInt;
+use num_rational::BigRational;
+use oracle_program::curve::calc_sell_price_from_num_iasset;
+use proptest::prelude::*;
+use std::convert::TryFrom;
+
+/// Reference output evaluated as the exact rational x·n/(y+n), floored so the AMM
+/// keeps the remainder
+fn reference_sell_price(x: u64, y: u64, n: u64) -> u64 {
+    let amount_out = BigRational::new(BigInt::from(x) * BigInt::from(n), BigInt::from(y) + BigInt::from(n));
+    u64::try_from(amount_out.floor().to_integer()).unwrap()
+}
+
+#[test]
+fn sells_into_a_balanced_pool() {
+    assert_eq!(calc_sell_price_from_num_iasset(1_000_000, 1_000_000, 1_000_000), 500_000);
+    assert_eq!(calc_sell_price_from_num_iasset(1_000, 100, 1), 9);
+}
+
+#[test]
+fn handles_empty_pools() {
+    assert_eq!(calc_sell_price_from_num_iasset(0, 0, 0), 0);
+    assert_eq!(calc_sell_price_from_num_iasset(1_000, 0, 0), 0);
+    assert_eq!(calc_sell_price_from_num_iasset(1_000, 0, 1), 1_000);
+    assert_eq!(calc_sell_price_from_num_iasset(0, 1_000, 1_000), 0);
+}
+
+#[test]
+fn handles_extreme_amounts() {
+    assert_eq!(calc_sell_price_from_num_iasset(u64::MAX, u64::MAX, u64::MAX), u64::MAX / 2);
+    assert_eq!(calc_sell_price_from_num_iasset(u64::MAX, 1, u64::MAX), u64::MAX - 1);
+}
+
+proptest! {
+    #[test]
+    fn matches_reference(x in 1..u64::MAX, y in 1..u64::MAX, n in any::<u64>()) {
+        prop_assert_eq!(calc_sell_price_from_num_iasset(x, y, n), reference_sell_price(x, y, n));
+    }
+
+    #[test]
+    fn never_decreases_the_invariant(x in any::<u64>(), y in 1..u64::MAX, n in any::<u64>()) {
+        let amount_out = calc_sell_price_from_num_iasset(x, y, n);
+        prop_assert!(amount_out <= x);
+        let k_after = (x - amount_out) as u128 * (y as u128 + n as u128);
+        prop_assert!(k_after >= x as u128 * y as u128);
+    }
+
+    #[test]
+    fn is_monotonic_in_amount_sold(x in any::<u64>(), y in any::<u64>(), n in 0..u64::MAX) {
+        prop_assert!(calc_sell_price_from_num_iasset(x, y, n) <= calc_sell_price_from_num_iasset(x, y, n + 1));
+    }
+}
@@ -0,0 +1,160 @@
+use oracle_program::{
+    curve::{calc_sell_price_from_num_iasset, curve_from_config, ConstantProduct, Curve, StableSwap, SwapDecimals},
+    error::OracleError,
+    math::Rounding,
+    states::CurveConfig,
+};
+use proptest::prelude::*;
+use solana_program::{program_error::ProgramError, program_pack::Pack, pubkey::Pubkey};
+
+/// Both mints at the same decimals, so balances compare one for one
+const DECIMALS: SwapDecimals = SwapDecimals { out_decimals: 6, in_decimals: 6 };
+
+fn curve_config(curve_type: u8, amplification: u64) -> CurveConfig {
+    CurveConfig {
+        is_initialized: true,
+        oracle_pubkey: Pubkey::new_unique(),
+        amm_pubkey: Pubkey::new_unique(),
+        curve_type,
+        amplification,
+        last_correction_slot: 0,
+        last_correction_price: 0,
+    }
+}
+
+#[test]
+fn constant_product_matches_sell_price() {
+    assert_eq!(ConstantProduct.sell_price(1_000, 100, 1, DECIMALS), Ok(calc_sell_price_from_num_iasset(1_000, 100, 1)));
+}
+
+#[test]
+fn stable_swap_trades_near_par_in_a_balanced_pool() {
+    let curve = StableSwap::new(100).unwrap();
+    let amount_out = curve.sell_price(1_000_000_000, 1_000_000_000, 1_000_000, DECIMALS).unwrap();
+    assert!(amount_out < 1_000_000);
+    assert!(amount_out > 999_900);
+    assert!(amount_out > ConstantProduct.sell_price(1_000_000_000, 1_000_000_000, 1_000_000, DECIMALS).unwrap());
+}
+
+#[test]
+fn stable_swap_flattens_with_amplification() {
+    let low = StableSwap::new(1).unwrap().sell_price(1_000_000, 1_000_000, 100_000, DECIMALS).unwrap();
+    let high = StableSwap::new(1_000).unwrap().sell_price(1_000_000, 1_000_000, 100_000, DECIMALS).unwrap();
+    assert!(low < high);
+}
+
+#[test]
+fn stable_swap_pays_nothing_from_an_empty_pool() {
+    let curve = StableSwap::new(100).unwrap();
+    assert_eq!(curve.sell_price(0, 1_000, 1_000, DECIMALS), Ok(0));
+    assert_eq!(curve.sell_price(1_000, 0, 1_000, DECIMALS), Ok(0));
+    assert_eq!(curve.sell_price(1_000, 1_000, 0, DECIMALS), Ok(0));
+}
+
+#[test]
+fn rejects_invalid_curve_config() {
+    assert_eq!(StableSwap::new(0).err(), Some(ProgramError::from(OracleError::InvalidCurve)));
+    assert_eq!(curve_from_config(2, 100).err(), Some(ProgramError::from(OracleError::InvalidCurve)));
+    assert_eq!(curve_config(1, 0).curve().err(), Some(ProgramError::from(OracleError::InvalidCurve)));
+    assert!(curve_config(0, 0).curve().is_ok());
+}
+
+#[test]
+fn curve_config_round_trips() {
+    let config = CurveConfig {
+        last_correction_slot: 77,
+        last_correction_price: 1_000_000_000,
+        ..curve_config(1, 250)
+    };
+    let mut data = vec![0; CurveConfig::LEN];
+    CurveConfig::pack(config, &mut data).unwrap();
+    let unpacked = CurveConfig::unpack(&data).unwrap();
+    assert_eq!(unpacked.curve_type, 1);
+    assert_eq!(unpacked.amplification, 250);
+    assert_eq!((unpacked.last_correction_slot, unpacked.last_correction_price), (77, 1_000_000_000));
+}
+
+proptest! {
+    #[test]
+    fn stable_swap_never_panics_or_drains_the_pool(
+        amplification in 1..10_000u64,
+        x in 1..u64::MAX / 4,
+        y in 1..u64::MAX / 4,
+        n in 0..u64::MAX / 4,
+    ) {
+        if let Ok(amount_out) = StableSwap::new(amplification).unwrap().sell_price(x, y, n, DECIMALS) {
+            prop_assert!(amount_out < x);
+        }
+    }
+
+    #[test]
+    fn stable_swap_is_monotonic_in_amount_sold(
+        amplification in 1..10_000u64,
+        x in 1_000..1_000_000_000_000u64,
+        y in 1_000..1_000_000_000_000u64,
+        n in 0..1_000_000_000_000u64,
+    ) {
+        let curve = StableSwap::new(amplification).unwrap();
+        prop_assert!(curve.sell_price(x, y, n, DECIMALS).unwrap() <= curve.sell_price(x, y, n * 2 + 1, DECIMALS).unwrap());
+    }
+}
+
+#[test]
+fn constant_product_rounds_in_the_requested_direction() {
+    //1000·1/3 = 333.3
+    assert_eq!(ConstantProduct.sell_price_rounded(1_000, 2, 1, DECIMALS, Rounding::RoundDown), Ok(333));
+    assert_eq!(ConstantProduct.sell_price_rounded(1_000, 2, 1, DECIMALS, Rounding::RoundUp), Ok(334));
+
+    //1000·1/4 = 250 exactly
+    assert_eq!(ConstantProduct.sell_price_rounded(1_000, 3, 1, DECIMALS, Rounding::RoundDown), Ok(250));
+    assert_eq!(ConstantProduct.sell_price_rounded(1_000, 3, 1, DECIMALS, Rounding::RoundUp), Ok(250));
+}
+
+#[test]
+fn stable_swap_rounds_up_by_at_most_the_newton_margin() {
+    let curve = StableSwap::new(100).unwrap();
+    let rounded_down = curve.sell_price_rounded(1_000_000_000, 1_000_000_000, 1_000_000, DECIMALS, Rounding::RoundDown).unwrap();
+    let rounded_up = curve.sell_price_rounded(1_000_000_000, 1_000_000_000, 1_000_000, DECIMALS, Rounding::RoundUp).unwrap();
+    assert_eq!(rounded_down, curve.sell_price(1_000_000_000, 1_000_000_000, 1_000_000, DECIMALS).unwrap());
+    assert!(rounded_up > rounded_down);
+    assert!(rounded_up - rounded_down <= 2);
+}
+
+#[test]
+fn stable_swap_balances_whole_tokens_across_mixed_decimals() {
+    let curve = StableSwap::new(100).unwrap();
+    //One million USDI at 6 decimals against one million iAsset at 9 decimals
+    let (usdi_amm_amount, iasset_amm_amount) = (1_000_000_000_000, 1_000_000_000_000_000);
+
+    let usdi_out = curve
+        .sell_price(usdi_amm_amount, iasset_amm_amount, 1_000_000_000, SwapDecimals::new(6, 9))
+        .unwrap();
+    assert!(usdi_out < 1_000_000);
+    assert!(usdi_out > 999_900);
+
+    let iasset_out = curve
+        .sell_price(iasset_amm_amount, usdi_amm_amount, 1_000_000, SwapDecimals::new(9, 6))
+        .unwrap();
+    assert!(iasset_out < 1_000_000_000);
+    assert!(iasset_out > 999_900_000);
+
+    //Same as the pool at 9 decimals on both sides, scaled back down to USDI base units
+    let scaled_usdi_out = curve
+        .sell_price(usdi_amm_amount * 1_000, iasset_amm_amount, 1_000_000_000, SwapDecimals::new(9, 9))
+        .unwrap();
+    assert_eq!(usdi_out, scaled_usdi_out / 1_000);
+}
+
+#[test]
+fn stable_swap_rounds_mixed_decimals_toward_the_pool() {
+    let curve = StableSwap::new(100).unwrap();
+    let decimals = SwapDecimals::new(6, 9);
+    let rounded_down = curve
+        .sell_price_rounded(1_000_000_000_000, 1_000_000_000_000_000, 1_234_567_891, decimals, Rounding::RoundDown)
+        .unwrap();
+    let rounded_up = curve
+        .sell_price_rounded(1_000_000_000_000, 1_000_000_000_000_000, 1_234_567_891, decimals, Rounding::RoundUp)
+        .unwrap();
+    assert!(rounded_up > rounded_down);
+    assert!(rounded_up - rounded_down <= 1);
+}
@@ -0,0 +1,166 @@
+mod common;
+
+use common::*;
+use oracle_program::{curve::calc_sell_price_from_num_iasset, error::OracleError, states::TradeQuote};
+use solana_program::{program_pack::Pack, pubkey::Pubkey};
+use solana_program_test::BanksClient;
+use solana_sdk::{
+    instruction::InstructionError,
+    signature::{Keypair, Signer},
+    transaction::TransactionError,
+};
+
+const TRADE_FEE_BPS: u64 = 30;
+
+/// {usdi_amount} times the trade fee, rounded up
+fn trade_fee(usdi_amount: u64) -> u64 {
+    (usdi_amount * TRADE_FEE_BPS).div_ceil(10_000)
+}
+
+/// A market with two AMMs at the oracle price, charging {TRADE_FEE_BPS}
+async fn fee_market(user_usdi_amount: u64) -> (Market, BanksClient, Keypair) {
+    let (market, program_test) = Market::new(user_usdi_amount, &[Pool::at_oracle_price(), Pool::at_oracle_price()], |oracle| {
+        oracle.trade_fee_bps = TRADE_FEE_BPS
+    });
+    let (banks_client, payer, _) = program_test.start().await;
+    (market, banks_client, payer)
+}
+
+#[tokio::test]
+async fn trade_usdi_collects_the_fee_on_the_usdi_paid() {
+    let user_usdi_amount = units(1_000, USDI_DECIMALS);
+    let (market, mut banks_client, payer) = fee_market(user_usdi_amount).await;
+
+    let usdi_amount = units(100, USDI_DECIMALS);
+    process(&mut banks_client, &payer, market.trade_usdi(0, usdi_amount, 0, None), &[&market.user]).await.unwrap();
+
+    assert_eq!(token_amount(&mut banks_client, market.fee_token_account).await, trade_fee(usdi_amount));
+    assert_eq!(
+        token_amount(&mut banks_client, market.user_usdi_token_account).await,
+        user_usdi_amount - usdi_amount - trade_fee(usdi_amount)
+    );
+}
+
+#[tokio::test]
+async fn trade_iasset_collects_the_fee_on_the_usdi_received() {
+    let (market, mut banks_client, payer) = fee_market(0).await;
+    let pool = Pool::at_oracle_price();
+
+    let iasset_amount = units(1, USDI_DECIMALS);
+    process(&mut banks_client, &payer, market.trade_iasset(0, iasset_amount, 0, None), &[&market.user]).await.unwrap();
+
+    let usdi_received = calc_sell_price_from_num_iasset(pool.usdi_amount, pool.iasset_amount, iasset_amount);
+    assert_eq!(token_amount(&mut banks_client, market.fee_token_account).await, trade_fee(usdi_received));
+    assert_eq!(
+        token_amount(&mut banks_client, market.user_usdi_token_account).await,
+        usdi_received - trade_fee(usdi_received)
+    );
+}
+
+#[tokio::test]
+async fn trade_route_charges_a_single_fee_from_the_intermediate_usdi() {
+    //The user holds no USDI of their own, so the route may only spend what the first leg returns
+    let (market, mut banks_client, payer) = fee_market(0).await;
+    let pool = Pool::at_oracle_price();
+
+    let iasset_amount = units(1, USDI_DECIMALS);
+    process(&mut banks_client, &payer, market.trade_route(0, 1, iasset_amount, 0, None), &[&market.user]).await.unwrap();
+
+    let usdi_received = calc_sell_price_from_num_iasset(pool.usdi_amount, pool.iasset_amount, iasset_amount);
+    let usdi_spent = usdi_received - trade_fee(usdi_received);
+    let iasset_bought = calc_sell_price_from_num_iasset(pool.iasset_amount, pool.usdi_amount, usdi_spent);
+    assert_eq!(token_amount(&mut banks_client, market.fee_token_account).await, trade_fee(usdi_received));
+    assert_eq!(token_amount(&mut banks_client, market.user_usdi_token_account).await, 0);
+    assert_eq!(
+        token_amount(&mut banks_client, market.amms[1].user_iasset_token_account).await,
+        pool.user_iasset_amount + iasset_bought
+    );
+}
+
+#[tokio::test]
+async fn quote_reports_what_trade_usdi_charges() {
+    let user_usdi_amount = units(1_000, USDI_DECIMALS);
+    let (market, mut banks_client, payer) = fee_market(user_usdi_amount).await;
+
+    let usdi_amount = units(100, USDI_DECIMALS);
+    let data = simulate_return_data(&mut banks_client, &payer, market.quote(0, usdi_amount, true)).await.unwrap();
+    let quote = TradeQuote::unpack_unchecked(&data).unwrap();
+    assert_eq!(quote.trade_fee, trade_fee(usdi_amount));
+
+    process(&mut banks_client, &payer, market.trade_usdi(0, usdi_amount, quote.amount_out, None), &[&market.user]).await.unwrap();
+    assert_eq!(
+        token_amount(&mut banks_client, market.user_usdi_token_account).await,
+        user_usdi_amount - usdi_amount - quote.trade_fee
+    );
+    assert_eq!(
+        token_amount(&mut banks_client, market.amms[0].user_iasset_token_account).await,
+        Pool::at_oracle_price().user_iasset_amount + quote.amount_out
+    );
+}
+
+#[tokio::test]
+async fn quote_reports_what_trade_iasset_pays_out_after_the_fee() {
+    let (market, mut banks_client, payer) = fee_market(0).await;
+    let pool = Pool::at_oracle_price();
+
+    let iasset_amount = units(1, USDI_DECIMALS);
+    let data = simulate_return_data(&mut banks_client, &payer, market.quote(0, iasset_amount, false)).await.unwrap();
+    let quote = TradeQuote::unpack_unchecked(&data).unwrap();
+    let usdi_received = calc_sell_price_from_num_iasset(pool.usdi_amount, pool.iasset_amount, iasset_amount);
+    assert_eq!(quote.trade_fee, trade_fee(usdi_received));
+    assert_eq!(quote.amount_out, usdi_received - trade_fee(usdi_received));
+
+    //The quoted output is exactly what the trade pays out, so it is a tight minimum
+    let err = process(&mut banks_client, &payer, market.trade_iasset(0, iasset_amount, quote.amount_out + 1, None), &[&market.user])
+        .await
+        .unwrap_err();
+    assert_eq!(err, oracle_error(OracleError::SlippageExceeded));
+    process(&mut banks_client, &payer, market.trade_iasset(0, iasset_amount, quote.amount_out, None), &[&market.user]).await.unwrap();
+    assert_eq!(token_amount(&mut banks_client, market.user_usdi_token_account).await, quote.amount_out);
+    assert_eq!(token_amount(&mut banks_client, market.fee_token_account).await, quote.trade_fee);
+}
+
+#[tokio::test]
+async fn withdraw_fees_pays_the_admin_collected_fees() {
+    let (market, mut banks_client, payer) = fee_market(units(1_000, USDI_DECIMALS)).await;
+    process(&mut banks_client, &payer, market.trade_usdi(0, units(100, USDI_DECIMALS), 0, None), &[&market.user]).await.unwrap();
+    let collected = token_amount(&mut banks_client, market.fee_token_account).await;
+
+    let destination = market.user_usdi_token_account;
+    let destination_before = token_amount(&mut banks_client, destination).await;
+    process(&mut banks_client, &payer, market.withdraw_fees(&market.admin.pubkey(), destination, collected), &[&market.admin])
+        .await
+        .unwrap();
+
+    assert_eq!(token_amount(&mut banks_client, market.fee_token_account).await, 0);
+    assert_eq!(token_amount(&mut banks_client, destination).await, destination_before + collected);
+}
+
+#[tokio::test]
+async fn withdraw_fees_rejects_a_non_admin() {
+    let (market, mut banks_client, payer) = fee_market(units(1_000, USDI_DECIMALS)).await;
+    process(&mut banks_client, &payer, market.trade_usdi(0, units(100, USDI_DECIMALS), 0, None), &[&market.user]).await.unwrap();
+    let collected = token_amount(&mut banks_client, market.fee_token_account).await;
+
+    let intruder = Keypair::new();
+    let err = process(
+        &mut banks_client,
+        &payer,
+        market.withdraw_fees(&intruder.pubkey(), market.user_usdi_token_account, collected),
+        &[&intruder],
+    )
+    .await
+    .unwrap_err();
+    assert_eq!(err, oracle_error(OracleError::InvalidAdmin));
+    assert_eq!(token_amount(&mut banks_client, market.fee_token_account).await, collected);
+}
+
+#[tokio::test]
+async fn withdraw_fees_only_pays_from_the_oracles_fee_account() {
+    let (market, mut banks_client, payer) = fee_market(units(1_000, USDI_DECIMALS)).await;
+    let mut withdraw_fees = market.withdraw_fees(&market.admin.pubkey(), market.user_usdi_token_account, 0);
+    withdraw_fees.accounts[2].pubkey = Pubkey::new_unique();
+
+    let err = process(&mut banks_client, &payer, withdraw_fees, &[&market.admin]).await.unwrap_err();
+    assert_eq!(err, TransactionError::InstructionError(0, InstructionError::InvalidAccountData));
+}
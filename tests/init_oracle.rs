@@ -0,0 +1,55 @@
+mod common;
+
+use common::*;
+use oracle_program::states::Oracle;
+use solana_program::{program_pack::Pack, pubkey::Pubkey};
+use solana_program_test::BanksClient;
+use solana_sdk::{
+    instruction::InstructionError,
+    signature::{Keypair, Signer},
+    transaction::TransactionError,
+};
+use spl_token::state::Account as TokenAccount;
+
+/// Initializes a market's zeroed Oracle with a fee token account owned by the
+/// initializer and the Pyth feed owned by {pyth_owner}
+async fn run_init_oracle(pyth_owner: Option<Pubkey>) -> (Market, Keypair, Result<(), TransactionError>, BanksClient) {
+    let initializer = Keypair::new();
+    let (market, mut program_test) = Market::new(0, &[], |_| ());
+    add_account(&mut program_test, market.oracle, vec![0; Oracle::LEN], market.program_id);
+    add_account(
+        &mut program_test,
+        market.fee_token_account,
+        token_account_data(market.usdi_mint, initializer.pubkey(), 0),
+        spl_token::id(),
+    );
+    if let Some(pyth_owner) = pyth_owner {
+        add_account(&mut program_test, market.pyth_product, pyth_product_data(&market.pyth_price), pyth_owner);
+        add_account(&mut program_test, market.pyth_price, pyth_price_data(MARKET_PYTH_PRICE), pyth_owner);
+    }
+    let (mut banks_client, payer, _) = program_test.start().await;
+
+    let result = process(&mut banks_client, &payer, market.init_oracle(&initializer.pubkey()), &[&initializer]).await;
+    (market, initializer, result, banks_client)
+}
+
+#[tokio::test]
+async fn init_oracle_binds_the_pyth_feed() {
+    let (market, initializer, result, mut banks_client) = run_init_oracle(None).await;
+    result.unwrap();
+
+    let oracle_info = oracle_info(&mut banks_client, market.oracle).await;
+    assert_eq!(oracle_info.admin_pubkey, initializer.pubkey());
+    assert_eq!(oracle_info.pyth_program_id, market.pyth_program_id);
+    assert_eq!(oracle_info.pyth_product_pubkey, market.pyth_product);
+    assert_eq!(oracle_info.pyth_price_pubkey, market.pyth_price);
+
+    let fee_token_account = banks_client.get_account(market.fee_token_account).await.unwrap().unwrap();
+    assert_eq!(TokenAccount::unpack(&fee_token_account.data).unwrap().owner, market.oracle_pda);
+}
+
+#[tokio::test]
+async fn init_oracle_rejects_pyth_accounts_not_owned_by_the_pyth_program() {
+    let (_, _, result, _) = run_init_oracle(Some(Pubkey::new_unique())).await;
+    assert_eq!(result.unwrap_err(), TransactionError::InstructionError(0, InstructionError::IncorrectProgramId));
+}
@@ -0,0 +1,152 @@
+use oracle_program::{error::OracleError, instruction::OracleInstruction};
+use solana_program::{program_error::ProgramError, pubkey::Pubkey};
+
+fn all_variants() -> Vec<OracleInstruction> {
+    vec![
+        OracleInstruction::InitOracle {
+            amm_program_id: Pubkey::new_unique(),
+            lpcp_program_id: Pubkey::new_unique(),
+            ucp_program_id: Pubkey::new_unique(),
+            pyth_program_id: Pubkey::new_unique(),
+            oracle_band_bps: 250,
+            trade_fee_bps: 30,
+            keeper_reward: 1_000,
+            keeper_reward_interval_slots: 150,
+            min_correction_interval_slots: 25,
+            correction_override_bps: 500,
+            max_correction_amount: 1_000_000,
+            max_correction_bps: 200,
+        },
+        OracleInstruction::TradeUSDI {
+            amount: 1_000_000,
+            min_amount_out: 990_000,
+            expiry_slot: Some(42),
+        },
+        OracleInstruction::TradeiAsset {
+            amount: u64::MAX,
+            min_amount_out: 0,
+            expiry_slot: None,
+        },
+        OracleInstruction::CollateralCorrection { num_amms: 3 },
+        OracleInstruction::TradeRoute {
+            amount: 5_000,
+            min_amount_out: 4_900,
+            expiry_slot: Some(1_000),
+        },
+        OracleInstruction::Quote {
+            amount: 10_000,
+            usdi_in: true,
+        },
+        OracleInstruction::Quote {
+            amount: 10_000,
+            usdi_in: false,
+        },
+        OracleInstruction::GetPrice,
+        OracleInstruction::SetPause { paused_flags: 0b101 },
+        OracleInstruction::WithdrawFees { amount: 77 },
+        OracleInstruction::SetCurve {
+            curve_type: 1,
+            amplification: 250,
+        },
+    ]
+}
+
+#[test]
+fn pack_unpack_round_trip() {
+    for instruction in all_variants() {
+        let packed = instruction.pack();
+        assert_eq!(OracleInstruction::unpack(&packed), Ok(instruction));
+    }
+}
+
+#[test]
+fn init_oracle_binds_program_ids() {
+    let amm_program_id = Pubkey::new_unique();
+    let lpcp_program_id = Pubkey::new_unique();
+    let ucp_program_id = Pubkey::new_unique();
+    let pyth_program_id = Pubkey::new_unique();
+
+    let mut data = vec![0];
+    data.extend_from_slice(amm_program_id.as_ref());
+    data.extend_from_slice(lpcp_program_id.as_ref());
+    data.extend_from_slice(ucp_program_id.as_ref());
+    data.extend_from_slice(pyth_program_id.as_ref());
+    data.extend_from_slice(&100u64.to_le_bytes());
+    data.extend_from_slice(&30u64.to_le_bytes());
+    data.extend_from_slice(&1_000u64.to_le_bytes());
+    data.extend_from_slice(&150u64.to_le_bytes());
+    data.extend_from_slice(&25u64.to_le_bytes());
+    data.extend_from_slice(&500u64.to_le_bytes());
+    data.extend_from_slice(&1_000_000u64.to_le_bytes());
+    data.extend_from_slice(&200u64.to_le_bytes());
+
+    assert_eq!(
+        OracleInstruction::unpack(&data),
+        Ok(OracleInstruction::InitOracle {
+            amm_program_id,
+            lpcp_program_id,
+            ucp_program_id,
+            pyth_program_id,
+            oracle_band_bps: 100,
+            trade_fee_bps: 30,
+            keeper_reward: 1_000,
+            keeper_reward_interval_slots: 150,
+            min_correction_interval_slots: 25,
+            correction_override_bps: 500,
+            max_correction_amount: 1_000_000,
+            max_correction_bps: 200,
+        })
+    );
+}
+
+#[test]
+fn unpack_rejects_short_data() {
+    for instruction in all_variants() {
+        let packed = instruction.pack();
+        for len in 1..packed.len() {
+            assert_eq!(
+                OracleInstruction::unpack(&packed[..len]),
+                Err(OracleError::InstructionDataTooShort.into())
+            );
+        }
+    }
+}
+
+#[test]
+fn unpack_rejects_trailing_data() {
+    for instruction in all_variants() {
+        let mut packed = instruction.pack();
+        packed.push(0);
+        assert_eq!(
+            OracleInstruction::unpack(&packed),
+            Err(OracleError::TrailingInstructionData.into())
+        );
+    }
+}
+
+#[test]
+fn unpack_rejects_invalid_option_tag() {
+    let mut packed = OracleInstruction::TradeUSDI {
+        amount: 1,
+        min_amount_out: 1,
+        expiry_slot: None,
+    }
+    .pack();
+    *packed.last_mut().unwrap() = 2;
+    assert_eq!(
+        OracleInstruction::unpack(&packed),
+        Err(ProgramError::from(OracleError::InvalidInstruction))
+    );
+}
+
+#[test]
+fn unpack_rejects_unknown_tag() {
+    assert_eq!(
+        OracleInstruction::unpack(&[]),
+        Err(ProgramError::from(OracleError::InvalidInstruction))
+    );
+    assert_eq!(
+        OracleInstruction::unpack(&[u8::MAX, 0, 0, 0, 0, 0, 0, 0, 0]),
+        Err(ProgramError::from(OracleError::InvalidInstruction))
+    );
+}
@@ -0,0 +1,142 @@
+//! Conservation of real token balances across trades and corrections run through
+//! the program; kept apart from tests/conservation.rs, since starting a program test
+//! replaces the syscall stubs the off-chain properties there rely on
+
+mod common;
+
+use common::*;
+use proptest::{prelude::*, test_runner::TestCaseError};
+use oracle_program::states::AMM;
+use solana_program::program_pack::Pack;
+use solana_program_test::{BanksClient, ProgramTestContext};
+use solana_sdk::signature::{Keypair, Signer};
+use spl_token::state::Mint;
+
+const TRADE_FEE_BPS: u64 = 30;
+
+/// A step of the market run through the program
+#[derive(Clone, Debug)]
+enum MarketStep {
+    TradeUsdi(u64),
+    TradeIasset(u64),
+    /// Moves the Pyth price, in Pyth units, and runs a correction
+    Correction(i64),
+}
+
+fn market_step() -> impl Strategy<Value = MarketStep> {
+    prop_oneof![
+        (1..=units(2_000, USDI_DECIMALS)).prop_map(MarketStep::TradeUsdi),
+        (1..=units(200, USDI_DECIMALS)).prop_map(MarketStep::TradeIasset),
+        //Oracle prices from 50 to 200 USDI
+        (5_000_000_000..=20_000_000_000i64).prop_map(MarketStep::Correction),
+    ]
+}
+
+/// Token balances of every account a trade or correction can touch
+#[derive(Clone, Copy, Debug, PartialEq)]
+struct MarketBalances {
+    user_usdi: u64,
+    user_iasset: u64,
+    amm_usdi: u64,
+    amm_iasset: u64,
+    iasset_supply: u64,
+    fees: u64,
+    keeper_reward: u64,
+    ucp_collateral: u64,
+    lpcp_collateral: u64,
+}
+
+impl MarketBalances {
+    async fn load(banks_client: &mut BanksClient, market: &Market) -> Self {
+        let amm = &market.amms[0];
+        let iasset_mint = banks_client.get_account(amm.iasset_mint).await.unwrap().unwrap();
+        MarketBalances {
+            user_usdi: token_amount(banks_client, market.user_usdi_token_account).await,
+            user_iasset: token_amount(banks_client, amm.user_iasset_token_account).await,
+            amm_usdi: token_amount(banks_client, amm.usdi_token_account).await,
+            amm_iasset: token_amount(banks_client, amm.iasset_token_account).await,
+            iasset_supply: Mint::unpack(&iasset_mint.data).unwrap().supply,
+            fees: token_amount(banks_client, market.fee_token_account).await,
+            keeper_reward: token_amount(banks_client, market.keeper_reward_token_account).await,
+            ucp_collateral: token_amount(banks_client, market.ucp_collateral).await,
+            lpcp_collateral: token_amount(banks_client, market.lpcp_collateral).await,
+        }
+    }
+
+    fn total_usdi(&self) -> u64 {
+        self.user_usdi + self.amm_usdi + self.fees + self.keeper_reward + self.ucp_collateral + self.lpcp_collateral
+    }
+}
+
+async fn set_pyth_price(context: &mut ProgramTestContext, market: &Market, pyth_price: i64) {
+    let mut account = context.banks_client.get_account(market.pyth_price).await.unwrap().unwrap();
+    account.data = pyth_price_data(pyth_price);
+    context.set_account(&market.pyth_price, &account.into());
+}
+
+/// Runs {steps} against a single-AMM market and checks its real token balances after each
+async fn check_market_conservation(steps: Vec<MarketStep>) -> Result<(), TestCaseError> {
+    let (market, program_test) = Market::new(units(10_000, USDI_DECIMALS), &[Pool::at_oracle_price()], |oracle| {
+        oracle.oracle_band_bps = 0;
+        oracle.trade_fee_bps = TRADE_FEE_BPS;
+        oracle.keeper_reward = units(1, USDI_DECIMALS);
+    });
+    let mut context = program_test.start_with_context().await;
+    let start = MarketBalances::load(&mut context.banks_client, &market).await;
+    let mut fees_charged = 0;
+
+    for step in steps {
+        let before = MarketBalances::load(&mut context.banks_client, &market).await;
+        let payer = Keypair::from_bytes(&context.payer.to_bytes()).unwrap();
+        let result = match step {
+            MarketStep::TradeUsdi(amount) => process(&mut context.banks_client, &payer, market.trade_usdi(0, amount, 0, None), &[&market.user]).await,
+            MarketStep::TradeIasset(amount) => {
+                process(&mut context.banks_client, &payer, market.trade_iasset(0, amount, 0, None), &[&market.user]).await
+            }
+            MarketStep::Correction(pyth_price) => {
+                set_pyth_price(&mut context, &market, pyth_price).await;
+                let keeper = Keypair::new();
+                process(&mut context.banks_client, &payer, market.collateral_correction(&[0], &keeper.pubkey()), &[&keeper]).await
+            }
+        };
+        let after = MarketBalances::load(&mut context.banks_client, &market).await;
+
+        if result.is_ok() {
+            match step {
+                MarketStep::TradeUsdi(_) | MarketStep::TradeIasset(_) => {
+                    //The fee is charged on the USDI that crossed the AMM, rounded up
+                    let usdi_traded = (after.amm_usdi as i128 - before.amm_usdi as i128).unsigned_abs() as u64;
+                    fees_charged += (usdi_traded * TRADE_FEE_BPS).div_ceil(10_000);
+
+                    //The spread the AMM reports moves from the UCP to the LPCP
+                    let amm_account = context.banks_client.get_account(market.amms[0].amm_account).await.unwrap().unwrap();
+                    let spread = AMM::unpack(&amm_account.data).unwrap().last_trade_spread;
+                    prop_assert_eq!(
+                        (after.ucp_collateral, after.lpcp_collateral),
+                        (before.ucp_collateral - spread, before.lpcp_collateral + spread)
+                    );
+                }
+                MarketStep::Correction(_) => {
+                    prop_assert_eq!((after.user_usdi, after.user_iasset, after.amm_usdi), (before.user_usdi, before.user_iasset, before.amm_usdi));
+                }
+            }
+        } else {
+            prop_assert_eq!(after, before);
+        }
+
+        prop_assert_eq!(after.total_usdi(), start.total_usdi());
+        prop_assert_eq!(after.ucp_collateral + after.lpcp_collateral, 2 * POOL_COLLATERAL);
+        prop_assert_eq!(after.fees + after.keeper_reward, fees_charged);
+        prop_assert_eq!(after.iasset_supply, after.amm_iasset + after.user_iasset);
+    }
+    Ok(())
+}
+
+proptest! {
+    #![proptest_config(ProptestConfig::with_cases(16))]
+
+    #[test]
+    fn trades_and_corrections_conserve_collateral_and_supply(steps in prop::collection::vec(market_step(), 1..12)) {
+        tokio::runtime::Runtime::new().unwrap().block_on(check_market_conservation(steps))?;
+    }
+}
@@ -0,0 +1,74 @@
+use oracle_program::{
+    error::OracleError,
+    math::{Decimal, Rounding},
+    processor::{signed_to_u64, to_u64},
+};
+use solana_program::program_error::ProgramError;
+
+#[test]
+fn multiplies_and_divides() {
+    let two_and_a_half = Decimal::from(5).try_div(Decimal::from(2)).unwrap();
+    assert_eq!(two_and_a_half.to_string(), "2.500000000000000000");
+    assert_eq!(two_and_a_half.try_mul(Decimal::from(4)).unwrap(), Decimal::from(10));
+    assert_eq!(Decimal::from(u64::MAX).try_mul_u64(u64::MAX).unwrap().try_div_u64(u64::MAX).unwrap(), Decimal::from(u64::MAX));
+}
+
+#[test]
+fn scales_by_powers_of_ten() {
+    assert_eq!(Decimal::from_fixed(1_500_000, 6).unwrap().to_string(), "1.500000000000000000");
+    assert_eq!(Decimal::from_fixed(15, 0).unwrap(), Decimal::from(15));
+    assert_eq!(Decimal::from_fixed(15, 19).unwrap().to_string(), "0.000000000000000001");
+    assert_eq!(Decimal::from_bps(9_000).to_string(), "0.900000000000000000");
+    assert_eq!(Decimal::from_fixed(1_500_000, 6).unwrap().try_to_base_units(9).unwrap(), Decimal::from(1_500_000_000));
+}
+
+#[test]
+fn parses_decimal_strings() {
+    assert_eq!("12.5".parse::<Decimal>().unwrap(), Decimal::from(25).try_div(Decimal::from(2)).unwrap());
+    assert_eq!("7".parse::<Decimal>().unwrap(), Decimal::from(7));
+    assert_eq!("0.000000000000000001".parse::<Decimal>().unwrap(), Decimal::from_scaled_val(1));
+    for invalid in ["", ".5", "1.", "-1", "1.2.3", "1e5", "0.0000000000000000001"] {
+        assert_eq!(invalid.parse::<Decimal>(), Err(ProgramError::InvalidArgument), "{}", invalid);
+    }
+}
+
+#[test]
+fn takes_square_roots() {
+    assert_eq!(Decimal::from(144).try_sqrt().unwrap(), Decimal::from(12));
+    assert_eq!(Decimal::from(2).try_sqrt().unwrap().to_string(), "1.414213562373095048");
+}
+
+#[test]
+fn rounds_explicitly() {
+    let value = Decimal::from(7).try_div(Decimal::from(2)).unwrap();
+    assert_eq!(value.try_floor_u64().unwrap(), 3);
+    assert_eq!(value.try_ceil_u64().unwrap(), 4);
+    assert_eq!(value.try_round_u64().unwrap(), 4);
+    assert_eq!(Decimal::from(3).try_ceil_u64().unwrap(), 3);
+}
+
+#[test]
+fn reports_overflow_and_underflow() {
+    assert_eq!(Decimal::from(1).try_sub(Decimal::from(2)), Err(ProgramError::from(OracleError::MathUnderflow)));
+    assert_eq!(Decimal::from(1).try_div(Decimal::zero()), Err(ProgramError::from(OracleError::MathOverflow)));
+    assert_eq!(
+        Decimal::from(u64::MAX).try_add(Decimal::one()).unwrap().try_floor_u64(),
+        Err(ProgramError::from(OracleError::MathOverflow))
+    );
+}
+
+#[test]
+fn converts_amounts_in_the_requested_direction() {
+    let value = Decimal::from(1).try_div(Decimal::from(3)).unwrap();
+    assert_eq!(to_u64(value, Rounding::RoundDown), Ok(0));
+    assert_eq!(to_u64(value, Rounding::RoundUp), Ok(1));
+    assert_eq!(to_u64(Decimal::from(5), Rounding::RoundDown), Ok(5));
+    assert_eq!(to_u64(Decimal::from(5), Rounding::RoundUp), Ok(5));
+}
+
+#[test]
+fn rejects_negative_amounts() {
+    assert_eq!(signed_to_u64(42), Ok(42));
+    assert_eq!(signed_to_u64(-42), Err(ProgramError::from(OracleError::NegativeAmount)));
+    assert_eq!(signed_to_u64(u64::MAX as i128 + 1), Err(ProgramError::from(OracleError::MathOverflow)));
+}
@@ -0,0 +1,95 @@
+mod common;
+
+use common::*;
+use oracle_program::{error::OracleError, states::Oracle};
+use solana_program::instruction::Instruction;
+use solana_program_test::BanksClient;
+use solana_sdk::{
+    signature::{Keypair, Signer},
+    transaction::TransactionError,
+};
+
+/// A market with two AMMs, where the admin has set {paused_flags}
+async fn paused_market(paused_flags: u8) -> (Market, BanksClient, Keypair) {
+    let (market, program_test) = Market::new(units(10_000, USDI_DECIMALS), &[Pool::at_oracle_price(), Pool::at_oracle_price()], |_| ());
+    let (mut banks_client, payer, _) = program_test.start().await;
+    process(&mut banks_client, &payer, market.set_pause(&market.admin.pubkey(), paused_flags), &[&market.admin]).await.unwrap();
+    (market, banks_client, payer)
+}
+
+/// Runs {instruction} on a market with {paused_flags} set, signed by the user and the admin
+async fn run_paused(paused_flags: u8, instruction: impl FnOnce(&Market) -> Instruction) -> Result<(), TransactionError> {
+    let (market, mut banks_client, payer) = paused_market(paused_flags).await;
+    let instruction = instruction(&market);
+    let signers: Vec<&Keypair> = vec![&market.user, &market.admin]
+        .into_iter()
+        .filter(|signer| instruction.accounts.iter().any(|meta| meta.is_signer && meta.pubkey == signer.pubkey()))
+        .collect();
+    process(&mut banks_client, &payer, instruction, &signers).await
+}
+
+#[tokio::test]
+async fn pausing_trading_blocks_trade_usdi() {
+    let trade_usdi = |market: &Market| market.trade_usdi(0, units(100, USDI_DECIMALS), 0, None);
+    run_paused(0, trade_usdi).await.unwrap();
+    assert_eq!(run_paused(Oracle::PAUSE_TRADING, trade_usdi).await.unwrap_err(), oracle_error(OracleError::Paused));
+}
+
+#[tokio::test]
+async fn pausing_trading_blocks_trade_iasset() {
+    let trade_iasset = |market: &Market| market.trade_iasset(0, units(1, USDI_DECIMALS), 0, None);
+    run_paused(0, trade_iasset).await.unwrap();
+    assert_eq!(run_paused(Oracle::PAUSE_TRADING, trade_iasset).await.unwrap_err(), oracle_error(OracleError::Paused));
+}
+
+#[tokio::test]
+async fn pausing_trading_blocks_trade_route() {
+    let trade_route = |market: &Market| market.trade_route(0, 1, units(1, USDI_DECIMALS), 0, None);
+    assert_eq!(run_paused(Oracle::PAUSE_TRADING, trade_route).await.unwrap_err(), oracle_error(OracleError::Paused));
+}
+
+#[tokio::test]
+async fn pausing_quote_blocks_quote() {
+    let (market, mut banks_client, payer) = paused_market(Oracle::PAUSE_QUOTE).await;
+    let err = simulate_return_data(&mut banks_client, &payer, market.quote(0, units(100, USDI_DECIMALS), true)).await.unwrap_err();
+    assert_eq!(err, oracle_error(OracleError::Paused));
+
+    //Other instructions keep running
+    simulate_return_data(&mut banks_client, &payer, market.get_price()).await.unwrap();
+}
+
+#[tokio::test]
+async fn pausing_get_price_blocks_get_price() {
+    let (market, mut banks_client, payer) = paused_market(Oracle::PAUSE_GET_PRICE).await;
+    let err = simulate_return_data(&mut banks_client, &payer, market.get_price()).await.unwrap_err();
+    assert_eq!(err, oracle_error(OracleError::Paused));
+
+    //Other instructions keep running
+    simulate_return_data(&mut banks_client, &payer, market.quote(0, units(100, USDI_DECIMALS), true)).await.unwrap();
+}
+
+#[tokio::test]
+async fn pausing_withdraw_fees_blocks_withdraw_fees() {
+    let withdraw_fees = |market: &Market| market.withdraw_fees(&market.admin.pubkey(), market.user_usdi_token_account, 0);
+    run_paused(0, withdraw_fees).await.unwrap();
+    assert_eq!(run_paused(Oracle::PAUSE_WITHDRAW_FEES, withdraw_fees).await.unwrap_err(), oracle_error(OracleError::Paused));
+}
+
+#[tokio::test]
+async fn set_pause_rejects_a_non_admin() {
+    let (market, mut banks_client, payer) = paused_market(0).await;
+    let err = process(&mut banks_client, &payer, market.set_pause(&market.user.pubkey(), Oracle::PAUSE_TRADING), &[&market.user])
+        .await
+        .unwrap_err();
+    assert_eq!(err, oracle_error(OracleError::InvalidAdmin));
+    assert_eq!(oracle_info(&mut banks_client, market.oracle).await.paused_flags, 0);
+}
+
+#[tokio::test]
+async fn set_pause_by_the_admin_replaces_the_flags() {
+    let (market, mut banks_client, payer) = paused_market(Oracle::PAUSE_TRADING | Oracle::PAUSE_QUOTE).await;
+    assert_eq!(oracle_info(&mut banks_client, market.oracle).await.paused_flags, Oracle::PAUSE_TRADING | Oracle::PAUSE_QUOTE);
+
+    process(&mut banks_client, &payer, market.set_pause(&market.admin.pubkey(), 0), &[&market.admin]).await.unwrap();
+    assert_eq!(oracle_info(&mut banks_client, market.oracle).await.paused_flags, 0);
+}
@@ -0,0 +1,75 @@
+mod common;
+
+use common::*;
+use oracle_program::{error::OracleError, states::{OraclePrice, TradeQuote}};
+use solana_program::{program_pack::Pack, pubkey::Pubkey};
+use solana_program_test::ProgramTest;
+
+/// A market with no AMMs, its Pyth feed priced at {pyth_price} ± {pyth_conf}, published at {pub_slot}
+fn priced_market(pyth_price: i64, pyth_conf: u64, pub_slot: u64) -> (Market, ProgramTest) {
+    let (market, mut program_test) = Market::new(0, &[], |_| ());
+    market.set_pyth_price(&mut program_test, pyth_price, pyth_conf, pub_slot);
+    (market, program_test)
+}
+
+#[tokio::test]
+async fn get_price_returns_the_normalized_oracle_price() {
+    let (market, program_test) = priced_market(1_234_500_000, 2_500_000, 77);
+    let (mut banks_client, payer, _) = program_test.start().await;
+
+    let data = simulate_return_data(&mut banks_client, &payer, market.get_price()).await.unwrap();
+    let oracle_price = OraclePrice::unpack_unchecked(&data).unwrap();
+
+    //12.345 ± 0.025 at 8 decimals, normalized to 9
+    assert_eq!(oracle_price.price, 12_345_000_000);
+    assert_eq!(oracle_price.conf, 25_000_000);
+    assert_eq!(oracle_price.publish_slot, 77);
+}
+
+#[tokio::test]
+async fn get_price_rejects_a_pyth_feed_other_than_the_oracles() {
+    let (market, mut program_test) = priced_market(1_000_000_000, 0, 0);
+    let (pyth_product, pyth_price) = (Pubkey::new_unique(), Pubkey::new_unique());
+    add_account(&mut program_test, pyth_product, pyth_product_data(&pyth_price), market.pyth_program_id);
+    add_account(&mut program_test, pyth_price, pyth_price_data(1_000_000_000), market.pyth_program_id);
+    let market = Market { pyth_product, pyth_price, ..market };
+    let (mut banks_client, payer, _) = program_test.start().await;
+
+    let err = simulate_return_data(&mut banks_client, &payer, market.get_price()).await.unwrap_err();
+    assert_eq!(err, oracle_error(OracleError::InvalidOraclePrice));
+}
+
+/// Quotes {amount} against an AMM priced at 100 USDI per iAsset, with 9 iAsset decimals
+async fn quote(amount: u64, usdi_in: bool) -> TradeQuote {
+    let (market, program_test) = Market::new(0, &[Pool::new(1_000_000, 10_000, 9)], |_| ());
+    let (mut banks_client, payer, _) = program_test.start().await;
+
+    let data = simulate_return_data(&mut banks_client, &payer, market.quote(0, amount, usdi_in)).await.unwrap();
+    TradeQuote::unpack_unchecked(&data).unwrap()
+}
+
+#[tokio::test]
+async fn quote_usdi_in_matches_the_hand_computed_quote() {
+    let trade_quote = quote(units(100, USDI_DECIMALS), true).await;
+
+    //10^13 * 10^8 / (10^12 + 10^8), floored
+    assert_eq!(trade_quote.amount_out, 999_900_009);
+    //10^8 * 10^13 / 10^12 at the AMM price, less the output
+    assert_eq!(trade_quote.spread, 99_991);
+    //100 USDI at 100 USDI per iAsset, in 9 decimal base units
+    assert_eq!(trade_quote.fair_value, 1_000_000_000);
+    assert_eq!(trade_quote.trade_fee, 0);
+}
+
+#[tokio::test]
+async fn quote_iasset_in_matches_the_hand_computed_quote() {
+    let trade_quote = quote(units(1, 9), false).await;
+
+    //10^12 * 10^9 / (10^13 + 10^9), floored
+    assert_eq!(trade_quote.amount_out, 99_990_000);
+    //10^9 * 10^12 / 10^13 at the AMM price, less the output
+    assert_eq!(trade_quote.spread, 10_000);
+    //One iAsset at 100 USDI, in 6 decimal base units
+    assert_eq!(trade_quote.fair_value, 100_000_000);
+    assert_eq!(trade_quote.trade_fee, 0);
+}
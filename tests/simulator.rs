@@ -0,0 +1,137 @@
+use oracle_program::{
+    curve::ConstantProduct,
+    error::OracleError,
+    math::{Decimal, Rounding},
+    processor::{calc_correction, calc_lpr, calc_lpr_usdi, AmmBalances},
+    simulator::{self, Simulation, SimulatorError, REPORT_HEADER},
+    states::Oracle,
+};
+use solana_program::{program_error::ProgramError, program_pack::Pack};
+
+const BALANCES: AmmBalances = AmmBalances {
+    usdi_amount: 1_000_000_000_000,
+    iasset_amount: 100_000_000_000,
+    iasset_supply: 101_000_000_000,
+    usdi_decimals: 6,
+    iasset_decimals: 6,
+};
+
+#[test]
+fn tick_replays_the_program_correction() {
+    let oracle_info = Oracle::unpack_unchecked(&[0; Oracle::LEN]).unwrap();
+    let correction = calc_correction(&oracle_info, &ConstantProduct, Decimal::from(10), &BALANCES).unwrap();
+
+    let mut simulation = Simulation::new(BALANCES).unwrap();
+    let report = simulation.tick(Decimal::from(10)).unwrap();
+
+    assert!(correction.mint_iasset);
+    assert_eq!(report.minted, correction.amount);
+    assert_eq!(report.burned, 0);
+    assert_eq!(simulation.balances.iasset_amount, BALANCES.iasset_amount + correction.amount);
+    assert_eq!(simulation.balances.iasset_supply, BALANCES.iasset_supply + correction.amount);
+    assert_eq!(report.target_lpr, Decimal::from(81));
+}
+
+#[test]
+fn tracks_collateral_moved_between_pools() {
+    let csv = "\
+        # 1M USDI and 100k iAsset, 1k iAsset held by users\n\
+        pool,1000000000000,100000000000,101000000000,6,6\n\
+        cap,0,1000\n\
+        tick,10\n\
+        buy,5000000000\n\
+        tick,10.5\n\
+        sell,500000000\n\
+        tick,35\n";
+    let reports = simulator::run(csv).unwrap();
+
+    assert_eq!(reports.len(), 3);
+    assert_eq!(reports.iter().map(|report| report.step).collect::<Vec<_>>(), vec![1, 2, 3]);
+    assert_eq!(reports[0].minted, 10_000_000_000);
+    assert_eq!(reports[1].price.to_string(), "10.500000000000000000");
+
+    let ucp_to_lpcp: u64 = reports.iter().map(|report| report.ucp_to_lpcp).sum();
+    let lpcp_to_ucp: u64 = reports.iter().map(|report| report.lpcp_to_ucp).sum();
+    assert!(lpcp_to_ucp > 0);
+    assert_eq!(ucp_to_lpcp, 0);
+    assert_eq!(reports[0].to_string().split(',').count(), REPORT_HEADER.split(',').count());
+}
+
+#[test]
+fn reports_negative_lpr_drift() {
+    let mut simulation = Simulation::new(AmmBalances { iasset_supply: BALANCES.iasset_amount, ..BALANCES }).unwrap();
+    simulation.set_caps(1, 0);
+    let report = simulation.tick(Decimal::from(100_000)).unwrap();
+    assert_eq!(report.burned, 1);
+    assert!(report.to_string().split(',').nth(5).unwrap().starts_with('-'));
+}
+
+#[test]
+fn rejects_malformed_rows() {
+    let parse_error = |csv: &str| match simulator::run(csv) {
+        Err(SimulatorError::Parse { line, .. }) => line,
+        other => panic!("expected a parse error, got {:?}", other),
+    };
+    assert_eq!(parse_error("swap,1"), 1);
+    assert_eq!(parse_error("pool,1,2,3,6"), 1);
+    assert_eq!(parse_error("pool,1,2,3,4294967302,6"), 1);
+    assert_eq!(parse_error("pool,1,2,3,6,6\n\ntick,ten"), 3);
+    assert_eq!(parse_error("pool,1,2,3,6,6\nbuy,-1"), 2);
+    assert_eq!(parse_error("tick,10"), 1);
+}
+
+#[test]
+fn rejects_selling_more_than_users_own() {
+    assert_eq!(
+        simulator::run("pool,1000,100,150,0,0\nsell,51"),
+        Err(SimulatorError::Program {
+            line: 2,
+            error: ProgramError::from(OracleError::MathUnderflow)
+        })
+    );
+}
+
+/// The correction amount for BALANCES at {price}, before rounding to base units
+fn unrounded_correction(price: u64) -> Decimal {
+    let lpr_usdi = calc_lpr_usdi(&ConstantProduct, BALANCES.usdi_amount, BALANCES.iasset_amount, 1_000_000_000, BALANCES.usdi_decimals, BALANCES.iasset_decimals, Rounding::RoundDown).unwrap();
+    let current_lpr = Decimal::from_fixed(lpr_usdi, BALANCES.usdi_decimals).unwrap();
+    let preferred_lpr = calc_lpr(Decimal::from(price)).unwrap();
+    let preferred_lpr_squared = preferred_lpr.try_mul(preferred_lpr).unwrap();
+    let difference = if current_lpr > preferred_lpr_squared {
+        current_lpr.try_sub(preferred_lpr_squared).unwrap()
+    } else {
+        preferred_lpr_squared.try_sub(current_lpr).unwrap()
+    };
+    difference.try_div(preferred_lpr).unwrap().try_to_base_units(BALANCES.iasset_decimals).unwrap()
+}
+
+#[test]
+fn correction_mints_rounded_down() {
+    let oracle_info = Oracle::unpack_unchecked(&[0; Oracle::LEN]).unwrap();
+    let correction = calc_correction(&oracle_info, &ConstantProduct, Decimal::from(16), &BALANCES).unwrap();
+    let unrounded = unrounded_correction(16);
+
+    assert!(correction.mint_iasset);
+    assert_ne!(unrounded.try_floor_u64(), unrounded.try_ceil_u64());
+    assert_eq!(correction.amount, unrounded.try_floor_u64().unwrap());
+}
+
+#[test]
+fn correction_burns_rounded_up() {
+    let oracle_info = Oracle::unpack_unchecked(&[0; Oracle::LEN]).unwrap();
+    let correction = calc_correction(&oracle_info, &ConstantProduct, Decimal::from(10_000), &BALANCES).unwrap();
+    let unrounded = unrounded_correction(10_000);
+
+    assert!(!correction.mint_iasset);
+    assert_ne!(unrounded.try_floor_u64(), unrounded.try_ceil_u64());
+    assert_eq!(correction.amount, unrounded.try_ceil_u64().unwrap());
+}
+
+#[test]
+fn lpr_rounds_in_the_requested_direction() {
+    //1M USDI and 100k iAsset with 1k iAsset sold back pays out 9900.99… USDI
+    let lpr_down = calc_lpr_usdi(&ConstantProduct, 1_000_000, 100_000, 1_000, 6, 6, Rounding::RoundDown).unwrap();
+    let lpr_up = calc_lpr_usdi(&ConstantProduct, 1_000_000, 100_000, 1_000, 6, 6, Rounding::RoundUp).unwrap();
+    assert_eq!(lpr_down, 1_000_000 - 9_901);
+    assert_eq!(lpr_up, 1_000_000 - 9_900);
+}
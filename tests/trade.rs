@@ -0,0 +1,230 @@
+mod common;
+
+use common::*;
+use oracle_program::{curve::calc_sell_price_from_num_iasset, error::OracleError};
+use solana_program::pubkey::Pubkey;
+use solana_program_test::BanksClient;
+use solana_sdk::{
+    instruction::InstructionError,
+    signature::Keypair,
+    transaction::TransactionError,
+};
+
+async fn start_market(pools: &[Pool], oracle_band_bps: u64) -> (Market, BanksClient, Keypair) {
+    let (market, program_test) = Market::new(units(10_000, USDI_DECIMALS), pools, |oracle| oracle.oracle_band_bps = oracle_band_bps);
+    let (banks_client, payer, _) = program_test.start().await;
+    (market, banks_client, payer)
+}
+
+#[tokio::test]
+async fn trade_inside_the_oracle_band_is_accepted_whatever_the_decimals() {
+    //100 USDI per iAsset with 9 decimals, a raw base-unit ratio of 0.1
+    let (market, mut banks_client, payer) = start_market(&[Pool::new(1_000_000, 10_000, 9)], MARKET_ORACLE_BAND_BPS).await;
+
+    process(&mut banks_client, &payer, market.trade_usdi(0, units(100, USDI_DECIMALS), 0, None), &[&market.user]).await.unwrap();
+    process(&mut banks_client, &payer, market.trade_iasset(0, units(1, 9), 0, None), &[&market.user]).await.unwrap();
+}
+
+#[tokio::test]
+async fn trade_sends_the_amm_spread_from_the_ucp_to_the_lpcp() {
+    let (market, mut banks_client, payer) = start_market(&[Pool::at_oracle_price()], MARKET_ORACLE_BAND_BPS).await;
+
+    process(&mut banks_client, &payer, market.trade_usdi(0, units(10_000, USDI_DECIMALS), 0, None), &[&market.user]).await.unwrap();
+
+    //10^10 USDI buys 10^20 / (10^12 + 10^10) = 99_009_900 iAsset, worth 9_900_990_000 USDI at 100 USDI
+    let spread = 99_010_000;
+    assert_eq!(token_amount(&mut banks_client, market.ucp_collateral).await, POOL_COLLATERAL - spread);
+    assert_eq!(token_amount(&mut banks_client, market.lpcp_collateral).await, POOL_COLLATERAL + spread);
+}
+
+#[tokio::test]
+async fn trade_outside_the_oracle_band_is_rejected() {
+    //110 USDI per iAsset, 10% above the oracle price
+    let (market, mut banks_client, payer) = start_market(&[Pool::new(1_100_000, 10_000, 9)], MARKET_ORACLE_BAND_BPS).await;
+
+    let err = process(&mut banks_client, &payer, market.trade_usdi(0, units(100, USDI_DECIMALS), 0, None), &[&market.user])
+        .await
+        .unwrap_err();
+    assert_eq!(err, oracle_error(OracleError::PriceOutsideOracleBand));
+    let err = process(&mut banks_client, &payer, market.trade_iasset(0, units(1, 9), 0, None), &[&market.user])
+        .await
+        .unwrap_err();
+    assert_eq!(err, oracle_error(OracleError::PriceOutsideOracleBand));
+}
+
+#[tokio::test]
+async fn oracle_band_of_zero_is_disabled() {
+    let (market, mut banks_client, payer) = start_market(&[Pool::new(1_100_000, 10_000, 9)], 0).await;
+
+    process(&mut banks_client, &payer, market.trade_usdi(0, units(100, USDI_DECIMALS), 0, None), &[&market.user]).await.unwrap();
+}
+
+#[tokio::test]
+async fn oracle_band_rejects_a_mint_other_than_the_amms() {
+    let (market, mut banks_client, payer) = start_market(&[Pool::at_oracle_price(), Pool::new(1_000_000, 10_000, 9)], MARKET_ORACLE_BAND_BPS).await;
+    let mut trade_usdi = market.trade_usdi(0, units(100, USDI_DECIMALS), 0, None);
+    trade_usdi.accounts[18].pubkey = market.amms[1].iasset_mint;
+
+    let err = process(&mut banks_client, &payer, trade_usdi, &[&market.user]).await.unwrap_err();
+    assert_eq!(err, oracle_error(OracleError::InvalidMintData));
+}
+
+#[tokio::test]
+async fn oracle_band_requires_its_accounts() {
+    let (market, mut banks_client, payer) = start_market(&[Pool::at_oracle_price(), Pool::at_oracle_price()], MARKET_ORACLE_BAND_BPS).await;
+    let mut trade_usdi = market.trade_usdi(0, units(100, USDI_DECIMALS), 0, None);
+    trade_usdi.accounts.drain(15..19);
+    let mut trade_route = market.trade_route(0, 1, units(1, USDI_DECIMALS), 0, None);
+    trade_route.accounts.drain(20..25);
+
+    for trade in [trade_usdi, trade_route] {
+        let err = process(&mut banks_client, &payer, trade, &[&market.user]).await.unwrap_err();
+        assert_eq!(err, TransactionError::InstructionError(0, InstructionError::NotEnoughAccountKeys));
+    }
+}
+
+#[tokio::test]
+async fn oracle_band_rejects_another_pyth_feed() {
+    let (market, mut banks_client, payer) = start_market(&[Pool::at_oracle_price(), Pool::at_oracle_price()], MARKET_ORACLE_BAND_BPS).await;
+    let mut trade_usdi = market.trade_usdi(0, units(100, USDI_DECIMALS), 0, None);
+    trade_usdi.accounts[15].pubkey = Pubkey::new_unique();
+    let mut trade_iasset = market.trade_iasset(0, units(1, USDI_DECIMALS), 0, None);
+    trade_iasset.accounts[16].pubkey = Pubkey::new_unique();
+    let mut trade_route = market.trade_route(0, 1, units(1, USDI_DECIMALS), 0, None);
+    trade_route.accounts[20].pubkey = Pubkey::new_unique();
+
+    for trade in [trade_usdi, trade_iasset, trade_route] {
+        let err = process(&mut banks_client, &payer, trade, &[&market.user]).await.unwrap_err();
+        assert_eq!(err, oracle_error(OracleError::InvalidOraclePrice));
+    }
+}
+
+/// iAsset bought by routing {iasset_amount} from the first pool into the second, without fees
+fn routed_amount(sell_pool: &Pool, buy_pool: &Pool, iasset_amount: u64) -> u64 {
+    let usdi_amount = calc_sell_price_from_num_iasset(sell_pool.usdi_amount, sell_pool.iasset_amount, iasset_amount);
+    calc_sell_price_from_num_iasset(buy_pool.iasset_amount, buy_pool.usdi_amount, usdi_amount)
+}
+
+#[tokio::test]
+async fn trade_route_swaps_one_iasset_for_another() {
+    let (sell_pool, buy_pool) = (Pool::at_oracle_price(), Pool::new(1_000_000, 10_000, 9));
+    let (market, mut banks_client, payer) = start_market(&[Pool::at_oracle_price(), Pool::new(1_000_000, 10_000, 9)], MARKET_ORACLE_BAND_BPS).await;
+
+    let iasset_amount = units(1, USDI_DECIMALS);
+    let iasset_bought = routed_amount(&sell_pool, &buy_pool, iasset_amount);
+    process(&mut banks_client, &payer, market.trade_route(0, 1, iasset_amount, iasset_bought, None), &[&market.user])
+        .await
+        .unwrap();
+
+    assert_eq!(
+        token_amount(&mut banks_client, market.amms[0].user_iasset_token_account).await,
+        sell_pool.user_iasset_amount - iasset_amount
+    );
+    assert_eq!(
+        token_amount(&mut banks_client, market.amms[1].user_iasset_token_account).await,
+        buy_pool.user_iasset_amount + iasset_bought
+    );
+    assert_eq!(token_amount(&mut banks_client, market.user_usdi_token_account).await, units(10_000, USDI_DECIMALS));
+}
+
+#[tokio::test]
+async fn trade_route_rejects_less_than_min_amount_out() {
+    let (sell_pool, buy_pool) = (Pool::at_oracle_price(), Pool::at_oracle_price());
+    let (market, mut banks_client, payer) = start_market(&[Pool::at_oracle_price(), Pool::at_oracle_price()], MARKET_ORACLE_BAND_BPS).await;
+
+    let iasset_amount = units(1, USDI_DECIMALS);
+    let iasset_bought = routed_amount(&sell_pool, &buy_pool, iasset_amount);
+    let err = process(&mut banks_client, &payer, market.trade_route(0, 1, iasset_amount, iasset_bought + 1, None), &[&market.user])
+        .await
+        .unwrap_err();
+    assert_eq!(err, oracle_error(OracleError::SlippageExceeded));
+}
+
+#[tokio::test]
+async fn trade_route_rejects_the_same_amm_on_both_legs() {
+    let (market, mut banks_client, payer) = start_market(&[Pool::at_oracle_price()], MARKET_ORACLE_BAND_BPS).await;
+    let err = process(&mut banks_client, &payer, market.trade_route(0, 0, units(1, USDI_DECIMALS), 0, None), &[&market.user])
+        .await
+        .unwrap_err();
+    assert_eq!(err, oracle_error(OracleError::DuplicateAmm));
+    assert_eq!(token_amount(&mut banks_client, market.amms[0].user_iasset_token_account).await, Pool::at_oracle_price().user_iasset_amount);
+}
+
+#[tokio::test]
+async fn trade_route_checks_the_oracle_band_on_both_legs() {
+    for pools in [
+        [Pool::new(1_100_000, 10_000, 9), Pool::at_oracle_price()],
+        [Pool::at_oracle_price(), Pool::new(1_100_000, 10_000, 9)],
+    ] {
+        let (market, mut banks_client, payer) = start_market(&pools, MARKET_ORACLE_BAND_BPS).await;
+        let err = process(&mut banks_client, &payer, market.trade_route(0, 1, units(1, USDI_DECIMALS), 0, None), &[&market.user])
+            .await
+            .unwrap_err();
+        assert_eq!(err, oracle_error(OracleError::PriceOutsideOracleBand));
+    }
+}
+
+#[tokio::test]
+async fn trade_usdi_rejects_less_than_min_amount_out() {
+    let pool = Pool::at_oracle_price();
+    let (market, mut banks_client, payer) = start_market(&[Pool::at_oracle_price()], MARKET_ORACLE_BAND_BPS).await;
+    let usdi_amount = units(100, USDI_DECIMALS);
+    let iasset_out = calc_sell_price_from_num_iasset(pool.iasset_amount, pool.usdi_amount, usdi_amount);
+
+    let err = process(&mut banks_client, &payer, market.trade_usdi(0, usdi_amount, iasset_out + 1, None), &[&market.user])
+        .await
+        .unwrap_err();
+    assert_eq!(err, oracle_error(OracleError::SlippageExceeded));
+    assert_eq!(token_amount(&mut banks_client, market.amms[0].user_iasset_token_account).await, pool.user_iasset_amount);
+
+    process(&mut banks_client, &payer, market.trade_usdi(0, usdi_amount, iasset_out, None), &[&market.user]).await.unwrap();
+    assert_eq!(
+        token_amount(&mut banks_client, market.amms[0].user_iasset_token_account).await,
+        pool.user_iasset_amount + iasset_out
+    );
+}
+
+#[tokio::test]
+async fn trade_iasset_rejects_less_than_min_amount_out() {
+    let pool = Pool::at_oracle_price();
+    let (market, mut banks_client, payer) = start_market(&[Pool::at_oracle_price()], MARKET_ORACLE_BAND_BPS).await;
+    let iasset_amount = units(1, USDI_DECIMALS);
+    let usdi_out = calc_sell_price_from_num_iasset(pool.usdi_amount, pool.iasset_amount, iasset_amount);
+
+    let err = process(&mut banks_client, &payer, market.trade_iasset(0, iasset_amount, usdi_out + 1, None), &[&market.user])
+        .await
+        .unwrap_err();
+    assert_eq!(err, oracle_error(OracleError::SlippageExceeded));
+    assert_eq!(token_amount(&mut banks_client, market.user_usdi_token_account).await, units(10_000, USDI_DECIMALS));
+
+    process(&mut banks_client, &payer, market.trade_iasset(0, iasset_amount, usdi_out, None), &[&market.user]).await.unwrap();
+    assert_eq!(
+        token_amount(&mut banks_client, market.user_usdi_token_account).await,
+        units(10_000, USDI_DECIMALS) + usdi_out
+    );
+}
+
+#[tokio::test]
+async fn trade_is_rejected_after_its_expiry_slot() {
+    let (market, program_test) = Market::new(units(10_000, USDI_DECIMALS), &[Pool::at_oracle_price()], |_| ());
+    let mut context = program_test.start_with_context().await;
+    context.warp_to_slot(100).unwrap();
+
+    for trade in [
+        market.trade_usdi(0, units(100, USDI_DECIMALS), 0, Some(99)),
+        market.trade_iasset(0, units(1, USDI_DECIMALS), 0, Some(99)),
+    ] {
+        let err = process(&mut context.banks_client, &context.payer, trade, &[&market.user]).await.unwrap_err();
+        assert_eq!(err, oracle_error(OracleError::TradeExpired));
+    }
+    let err = process(&mut context.banks_client, &context.payer, market.trade_route(0, 0, units(1, USDI_DECIMALS), 0, Some(99)), &[&market.user])
+        .await
+        .unwrap_err();
+    assert_eq!(err, oracle_error(OracleError::TradeExpired));
+    assert_eq!(token_amount(&mut context.banks_client, market.user_usdi_token_account).await, units(10_000, USDI_DECIMALS));
+
+    //A trade may still land in its expiry slot
+    process(&mut context.banks_client, &context.payer, market.trade_usdi(0, units(100, USDI_DECIMALS), 0, Some(100)), &[&market.user])
+        .await
+        .unwrap();
+}